@@ -0,0 +1,348 @@
+use crate::{AuthClaims, AuthError, AuthService, CredentialLocation, MiddlewareFuture, TokenType};
+use async_trait::async_trait;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+// Hex-encoded SHA-256 of a raw API key; only this digest is ever stored
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+// A stored API key and the identity it authenticates as
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyRecord {
+    pub key_hash: String,
+    pub subject: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(rename = "type", default = "system_token_type")]
+    pub token_type: TokenType,
+    // Unix timestamp after which the key is rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<usize>,
+    // Additional claims, e.g. `tenant_id`, for services using custom claims
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+fn system_token_type() -> TokenType {
+    TokenType::System
+}
+
+impl ApiKeyRecord {
+    pub fn new(key: &str, subject: &str, roles: Vec<String>) -> Self {
+        Self {
+            key_hash: hash_api_key(key),
+            subject: subject.to_string(),
+            roles,
+            token_type: TokenType::System,
+            expires_at: None,
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    pub fn with_token_type(mut self, token_type: TokenType) -> Self {
+        self.token_type = token_type;
+        self
+    }
+
+    pub fn with_expiry(mut self, expires_at: usize) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn with_claim(mut self, name: &str, value: serde_json::Value) -> Self {
+        self.extra.insert(name.to_string(), value);
+        self
+    }
+
+    // Claims document equivalent to a token issued for this key
+    pub(crate) fn claims(&self) -> serde_json::Value {
+        let mut claims = self.extra.clone();
+        claims.insert("sub".to_string(), self.subject.clone().into());
+        claims.insert("roles".to_string(), self.roles.clone().into());
+        claims.insert(
+            "exp".to_string(),
+            self.expires_at.unwrap_or(i64::MAX as usize).into(),
+        );
+        claims.insert(
+            "type".to_string(),
+            serde_json::to_value(self.token_type).expect("token type serializes"),
+        );
+        serde_json::Value::Object(claims)
+    }
+}
+
+// Lookup of API keys by their hash
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    async fn find(&self, key_hash: &str) -> Result<Option<ApiKeyRecord>, AuthError>;
+}
+
+#[derive(Default)]
+pub struct InMemoryApiKeyStore {
+    records: RwLock<HashMap<String, ApiKeyRecord>>,
+}
+
+impl InMemoryApiKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, record: ApiKeyRecord) {
+        self.records
+            .write()
+            .expect("api key store lock poisoned")
+            .insert(record.key_hash.clone(), record);
+    }
+
+    pub fn revoke(&self, key: &str) -> bool {
+        self.records
+            .write()
+            .expect("api key store lock poisoned")
+            .remove(&hash_api_key(key))
+            .is_some()
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for InMemoryApiKeyStore {
+    async fn find(&self, key_hash: &str) -> Result<Option<ApiKeyRecord>, AuthError> {
+        Ok(self
+            .records
+            .read()
+            .expect("api key store lock poisoned")
+            .get(key_hash)
+            .cloned())
+    }
+}
+
+// YAML or JSON list of `ApiKeyRecord`s, read at load time and on `reload`
+pub struct FileApiKeyStore {
+    path: PathBuf,
+    records: InMemoryApiKeyStore,
+}
+
+impl FileApiKeyStore {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, AuthError> {
+        let store = Self {
+            path: path.into(),
+            records: InMemoryApiKeyStore::new(),
+        };
+        store.reload()?;
+        Ok(store)
+    }
+
+    pub fn reload(&self) -> Result<(), AuthError> {
+        let contents = std::fs::read_to_string(&self.path)
+            .map_err(|e| AuthError::KeyProvider(format!("{}: {}", self.path.display(), e)))?;
+        let records: Vec<ApiKeyRecord> = serde_yaml::from_str(&contents)
+            .map_err(|e| AuthError::KeyProvider(format!("{}: {}", self.path.display(), e)))?;
+
+        let mut current = self
+            .records
+            .records
+            .write()
+            .expect("api key store lock poisoned");
+        *current = records
+            .into_iter()
+            .map(|record| (record.key_hash.clone(), record))
+            .collect();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for FileApiKeyStore {
+    async fn find(&self, key_hash: &str) -> Result<Option<ApiKeyRecord>, AuthError> {
+        self.records.find(key_hash).await
+    }
+}
+
+// API key authentication middleware factory; attaches the key's claims like a bearer token
+pub fn require_api_key<C: AuthClaims>(
+    location: CredentialLocation,
+) -> impl Fn(State<AuthService<C>>, Request, Next) -> MiddlewareFuture + Clone {
+    move |State(auth_service): State<AuthService<C>>, mut request: Request, next: Next| {
+        let location = location.clone();
+        Box::pin(async move {
+            let key = location.extract(request.headers(), request.uri().query());
+            let csrf = auth_service.token_sources.check_csrf(
+                &location,
+                request.method(),
+                request.headers(),
+            );
+            let result = match (key, csrf) {
+                (None, _) => Err(AuthError::MissingToken),
+                (Some(_), Err(error)) => Err(error),
+                (Some(key), Ok(())) => auth_service.verify_api_key(&key).await,
+            };
+            let request_tenant = auth_service.request_tenant(&request);
+            let result = result.and_then(|claims| {
+                crate::check_tenant(&claims, request_tenant.as_deref())?;
+                Ok(claims)
+            });
+            auth_service.record_audit(&request, &result);
+
+            crate::insert_claims(&mut request, result?);
+            Ok(next.run(request).await)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[tokio::test]
+    async fn test_api_key_authentication() {
+        let store = Arc::new(InMemoryApiKeyStore::new());
+        store.insert(ApiKeyRecord::new(
+            "sk_live_reporting",
+            "reporting-job",
+            vec!["reports".to_string()],
+        ));
+        store.insert(ApiKeyRecord::new("sk_expired", "old-job", vec![]).with_expiry(1));
+        store.insert(
+            ApiKeyRecord::new("sk_acme", "acme-sync", vec!["reports".to_string()])
+                .with_claim("tenant", serde_json::json!("acme")),
+        );
+        let audit_path =
+            std::env::temp_dir().join(format!("api-key-audit-{}.jsonl", uuid::Uuid::new_v4()));
+        let auth_service = AuthService::new(b"test_secret")
+            .with_api_key_store(store.clone())
+            .with_audit_sink(JsonLinesAuditSink::open(&audit_path).unwrap());
+
+        let claims = auth_service
+            .verify_api_key("sk_live_reporting")
+            .await
+            .unwrap();
+        assert_eq!(claims.sub, "reporting-job");
+        assert_eq!(claims.token_type, TokenType::System);
+        assert!(matches!(
+            auth_service.verify_api_key("sk_unknown").await,
+            Err(AuthError::InvalidApiKey)
+        ));
+        assert!(matches!(
+            auth_service.verify_api_key("sk_expired").await,
+            Err(AuthError::TokenExpired)
+        ));
+
+        let app = axum::Router::new()
+            .route(
+                "/reports",
+                axum::routing::get(|AuthUser(claims): AuthUser| async move { claims.sub }),
+            )
+            .route(
+                "/tenant",
+                axum::routing::get(|Tenant(tenant): Tenant| async move { tenant }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                auth_service.clone(),
+                require_roles(vec!["reports".to_string()]),
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                auth_service.clone(),
+                require_api_key(CredentialLocation::Header("x-api-key".to_string())),
+            ))
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let url = format!("http://{}/reports", addr);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let response = client
+            .get(format!("http://{}/tenant", addr))
+            .header("x-api-key", "sk_acme")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "acme");
+
+        let response = client
+            .get(&url)
+            .header("x-api-key", "sk_live_reporting")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "reporting-job");
+
+        assert!(store.revoke("sk_live_reporting"));
+        let response = client
+            .get(&url)
+            .header("x-api-key", "sk_live_reporting")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+
+        let events: Vec<AuditEvent> = std::fs::read_to_string(&audit_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        std::fs::remove_file(&audit_path).unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].subject.as_deref(), Some("acme-sync"));
+        assert_eq!(events[2].outcome, AuditOutcome::Failure);
+        assert_eq!(events[2].reason.as_deref(), Some("invalid_api_key"));
+    }
+
+    #[test]
+    fn test_file_api_key_store() {
+        let path = std::env::temp_dir().join(format!("api-keys-{}.json", uuid::Uuid::new_v4()));
+        let records = vec![
+            ApiKeyRecord::new("sk_tenant", "tenant-sync", vec![])
+                .with_claim("tenant_id", serde_json::json!("acme")),
+        ];
+        std::fs::write(&path, serde_json::to_string(&records).unwrap()).unwrap();
+
+        let stored: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(stored[0]["key_hash"], hash_api_key("sk_tenant"));
+        assert!(!stored.to_string().contains("\"sk_tenant\""));
+
+        let store = FileApiKeyStore::load(&path).unwrap();
+        let record = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(store.find(&hash_api_key("sk_tenant")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.subject, "tenant-sync");
+        assert_eq!(record.extra["tenant_id"], "acme");
+
+        std::fs::write(
+            &path,
+            format!(
+                "- key_hash: {}\n  subject: nightly-export\n  roles: [reports]\n",
+                hash_api_key("sk_yaml")
+            ),
+        )
+        .unwrap();
+        store.reload().unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        assert!(
+            runtime
+                .block_on(store.find(&hash_api_key("sk_tenant")))
+                .unwrap()
+                .is_none()
+        );
+        let record = runtime
+            .block_on(store.find(&hash_api_key("sk_yaml")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.subject, "nightly-export");
+        assert_eq!(record.token_type, TokenType::System);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
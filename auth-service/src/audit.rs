@@ -0,0 +1,268 @@
+use crate::{AuthClaims, AuthError};
+use axum::extract::{ConnectInfo, MatchedPath};
+use axum::http::Request;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+// One authentication attempt against a protected route, or the refusal of an authenticated
+// request by an authorization check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    // RFC 3339
+    pub timestamp: String,
+    pub outcome: AuditOutcome,
+    // Only known once the credentials were verified, so also set for refusals
+    pub subject: Option<String>,
+    // `AuthError::code` of a failure
+    pub reason: Option<String>,
+    pub method: String,
+    // Route template such as `/tasks/{id}`, or the request path outside a router
+    pub route: String,
+    pub client_ip: Option<String>,
+    pub token_id: Option<String>,
+}
+
+impl AuditEvent {
+    pub(crate) fn new<B, C: AuthClaims>(
+        request: &Request<B>,
+        result: &Result<C, AuthError>,
+    ) -> Self {
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str())
+            .unwrap_or_else(|| request.uri().path());
+        // The peer address needs `into_make_service_with_connect_info`; behind a proxy the
+        // forwarded address is recorded instead
+        let client_ip = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string())
+            .or_else(|| {
+                request
+                    .headers()
+                    .get("x-forwarded-for")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.split(',').next())
+                    .map(|ip| ip.trim().to_string())
+            });
+
+        let (outcome, subject, reason, token_id) = match result {
+            Ok(claims) => (
+                AuditOutcome::Success,
+                Some(claims.subject().to_string()),
+                None,
+                claims.token_id().map(str::to_string),
+            ),
+            Err(error) => (
+                AuditOutcome::Failure,
+                None,
+                Some(error.code().to_string()),
+                None,
+            ),
+        };
+
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            outcome,
+            subject,
+            reason,
+            method: request.method().to_string(),
+            route: route.to_string(),
+            client_ip,
+            token_id,
+        }
+    }
+
+    pub(crate) fn denied<B, C: AuthClaims>(
+        request: &Request<B>,
+        claims: &C,
+        error: &AuthError,
+    ) -> Self {
+        Self {
+            outcome: AuditOutcome::Failure,
+            reason: Some(error.code().to_string()),
+            ..Self::new(request, &Ok(claims.clone()))
+        }
+    }
+}
+
+// Destination of audit events; recording must not fail the request it describes
+pub trait AuditSink: Send + Sync {
+    fn record(&self, event: &AuditEvent);
+}
+
+// Emits events to the `auth_audit` tracing target, failures at warn level
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+    fn record(&self, event: &AuditEvent) {
+        match event.outcome {
+            AuditOutcome::Success => tracing::info!(
+                target: "auth_audit",
+                outcome = "success",
+                subject = event.subject.as_deref(),
+                method = %event.method,
+                route = %event.route,
+                client_ip = event.client_ip.as_deref(),
+                token_id = event.token_id.as_deref(),
+                "authenticated"
+            ),
+            AuditOutcome::Failure => tracing::warn!(
+                target: "auth_audit",
+                outcome = "failure",
+                reason = event.reason.as_deref(),
+                subject = event.subject.as_deref(),
+                method = %event.method,
+                route = %event.route,
+                client_ip = event.client_ip.as_deref(),
+                "authentication failed"
+            ),
+        }
+    }
+}
+
+// Appends one JSON object per event, for review or shipping to a log pipeline
+pub struct JsonLinesAuditSink {
+    file: Mutex<File>,
+}
+
+impl JsonLinesAuditSink {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AuthError> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| AuthError::AuditLogUnavailable(format!("{}: {}", path.display(), e)))?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl AuditSink for JsonLinesAuditSink {
+    fn record(&self, event: &AuditEvent) {
+        let mut line = serde_json::to_vec(event).expect("audit event serializes");
+        line.push(b'\n');
+        // One write per line keeps concurrent appenders from interleaving
+        let result = self
+            .file
+            .lock()
+            .expect("audit log lock poisoned")
+            .write_all(&line);
+        if let Err(error) = result {
+            tracing::error!(target: "auth_audit", %error, "failed to write audit event");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[tokio::test]
+    async fn test_audit_events() {
+        let path = std::env::temp_dir().join(format!("audit-{}.jsonl", uuid::Uuid::new_v4()));
+        let auth_service = AuthService::new(b"test_secret")
+            .with_audit_sink(JsonLinesAuditSink::open(&path).unwrap());
+        let token = auth_service
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        let token_id = auth_service.verify_token(&token).unwrap().jti;
+
+        let app = axum::Router::new()
+            .route(
+                "/tasks/{id}",
+                axum::routing::get(|| async { "ok" }).layer(axum::middleware::from_fn_with_state(
+                    auth_service.clone(),
+                    user_auth_middleware,
+                )),
+            )
+            .route(
+                "/admin",
+                axum::routing::get(|| async { "ok" })
+                    .layer(axum::middleware::from_fn_with_state(
+                        auth_service.clone(),
+                        require_roles(vec!["admin".to_string()]),
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        auth_service.clone(),
+                        user_auth_middleware,
+                    )),
+            )
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let url = format!("http://{}/tasks/7", addr);
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+            )
+            .await
+            .unwrap()
+        });
+
+        let client = reqwest::Client::new();
+        let response = client.get(&url).bearer_auth(&token).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let response = client
+            .get(&url)
+            .bearer_auth("garbage")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        let response = client
+            .get(format!("http://{}/admin", addr))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+
+        let events: Vec<AuditEvent> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(events.len(), 5);
+
+        assert_eq!(events[0].outcome, AuditOutcome::Success);
+        assert_eq!(events[0].subject.as_deref(), Some("user123"));
+        assert_eq!(events[0].token_id, token_id);
+        assert_eq!(events[0].method, "GET");
+        assert_eq!(events[0].route, "/tasks/{id}");
+        assert_eq!(events[0].client_ip.as_deref(), Some("127.0.0.1"));
+
+        assert_eq!(events[1].outcome, AuditOutcome::Failure);
+        assert_eq!(events[1].subject, None);
+        assert_eq!(events[1].reason.as_deref(), Some("malformed_token"));
+        assert_eq!(events[2].reason.as_deref(), Some("missing_token"));
+
+        // Authenticated, then refused by the role check
+        assert_eq!(events[3].outcome, AuditOutcome::Success);
+        assert_eq!(events[4].outcome, AuditOutcome::Failure);
+        assert_eq!(events[4].subject.as_deref(), Some("user123"));
+        assert_eq!(events[4].token_id, token_id);
+        assert_eq!(events[4].reason.as_deref(), Some("insufficient_role"));
+        assert_eq!(events[4].route, "/admin");
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{Claims, TokenType};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::ops::{Deref, DerefMut};
+
+// Claims carried by tokens that `AuthService` verifies and the middlewares attach to requests
+pub trait AuthClaims: Serialize + DeserializeOwned + Clone + Send + Sync + 'static {
+    fn subject(&self) -> &str;
+    fn roles(&self) -> &[String];
+    fn token_type(&self) -> TokenType;
+
+    // `iat`, checked against the configured leeway when present
+    fn issued_at(&self) -> Option<usize> {
+        None
+    }
+
+    // `jti`, needed to revoke the token
+    fn token_id(&self) -> Option<&str> {
+        None
+    }
+
+    // `exp`, how long a revocation has to be remembered
+    fn expires_at(&self) -> Option<usize> {
+        None
+    }
+
+    // `tenant`, selecting the verification keys and roles of a multi-tenant service
+    fn tenant_id(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl AuthClaims for Claims {
+    fn subject(&self) -> &str {
+        &self.sub
+    }
+
+    fn roles(&self) -> &[String] {
+        &self.roles
+    }
+
+    fn token_type(&self) -> TokenType {
+        self.token_type
+    }
+
+    fn issued_at(&self) -> Option<usize> {
+        self.iat
+    }
+
+    fn token_id(&self) -> Option<&str> {
+        self.jti.as_deref()
+    }
+
+    fn expires_at(&self) -> Option<usize> {
+        Some(self.exp)
+    }
+
+    fn tenant_id(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+}
+
+// Standard claims extended with application fields serialized alongside them,
+// e.g. `CustomClaims<TenantClaims>` for tokens carrying `tenant_id` and `email`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CustomClaims<T> {
+    #[serde(flatten)]
+    pub standard: Claims,
+    #[serde(flatten)]
+    pub custom: T,
+}
+
+impl<T> CustomClaims<T> {
+    pub fn new(standard: Claims, custom: T) -> Self {
+        Self { standard, custom }
+    }
+}
+
+impl<T> AuthClaims for CustomClaims<T>
+where
+    T: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    fn subject(&self) -> &str {
+        self.standard.subject()
+    }
+
+    fn roles(&self) -> &[String] {
+        self.standard.roles()
+    }
+
+    fn token_type(&self) -> TokenType {
+        self.standard.token_type()
+    }
+
+    fn issued_at(&self) -> Option<usize> {
+        self.standard.issued_at()
+    }
+
+    fn token_id(&self) -> Option<&str> {
+        self.standard.token_id()
+    }
+
+    fn expires_at(&self) -> Option<usize> {
+        self.standard.expires_at()
+    }
+
+    fn tenant_id(&self) -> Option<&str> {
+        self.standard.tenant_id()
+    }
+}
+
+impl<T> Deref for CustomClaims<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.custom
+    }
+}
+
+impl<T> DerefMut for CustomClaims<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.custom
+    }
+}
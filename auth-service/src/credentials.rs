@@ -0,0 +1,172 @@
+use crate::{AuthError, TokenType};
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{OnceLock, RwLock};
+
+// Argon2id PHC string for a password or client secret; only this hash is ever stored
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    let salt = SaltString::encode_b64(uuid::Uuid::new_v4().as_bytes())
+        .map_err(|e| AuthError::InvalidKey(e.to_string()))?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AuthError::InvalidKey(e.to_string()))
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+// A user (password grant) or client (client credentials grant), told apart by `type`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credential {
+    // Username, or client id
+    pub username: String,
+    pub password_hash: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(rename = "type", default = "user_token_type")]
+    pub token_type: TokenType,
+    // Additional claims of issued tokens, e.g. `tenant`
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+fn user_token_type() -> TokenType {
+    TokenType::User
+}
+
+impl Credential {
+    pub fn user(username: &str, password: &str, roles: Vec<String>) -> Result<Self, AuthError> {
+        Ok(Self {
+            username: username.to_string(),
+            password_hash: hash_password(password)?,
+            roles,
+            token_type: TokenType::User,
+            extra: serde_json::Map::new(),
+        })
+    }
+
+    pub fn client(client_id: &str, secret: &str, roles: Vec<String>) -> Result<Self, AuthError> {
+        Ok(Self {
+            token_type: TokenType::System,
+            ..Self::user(client_id, secret, roles)?
+        })
+    }
+
+    pub fn with_claim(mut self, name: &str, value: serde_json::Value) -> Self {
+        self.extra.insert(name.to_string(), value);
+        self
+    }
+
+    // Checks the password, costing the same time whether or not the credential exists
+    pub(crate) fn verify(credential: Option<&Self>, password: &str) -> bool {
+        static UNKNOWN: OnceLock<String> = OnceLock::new();
+        match credential {
+            Some(credential) => verify_password(password, &credential.password_hash),
+            None => {
+                let hash = UNKNOWN.get_or_init(|| {
+                    hash_password(&uuid::Uuid::new_v4().to_string()).expect("argon2 hashes")
+                });
+                verify_password(password, hash);
+                false
+            }
+        }
+    }
+}
+
+// Lookup of users and clients by name
+#[async_trait]
+pub trait CredentialStore: Send + Sync {
+    async fn find(&self, username: &str) -> Result<Option<Credential>, AuthError>;
+}
+
+#[derive(Default)]
+pub struct InMemoryCredentialStore {
+    credentials: RwLock<HashMap<String, Credential>>,
+}
+
+impl InMemoryCredentialStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, credential: Credential) {
+        self.credentials
+            .write()
+            .expect("credential store lock poisoned")
+            .insert(credential.username.clone(), credential);
+    }
+
+    pub fn remove(&self, username: &str) -> bool {
+        self.credentials
+            .write()
+            .expect("credential store lock poisoned")
+            .remove(username)
+            .is_some()
+    }
+}
+
+#[async_trait]
+impl CredentialStore for InMemoryCredentialStore {
+    async fn find(&self, username: &str) -> Result<Option<Credential>, AuthError> {
+        Ok(self
+            .credentials
+            .read()
+            .expect("credential store lock poisoned")
+            .get(username)
+            .cloned())
+    }
+}
+
+// YAML or JSON list of `Credential`s, read at load time and on `reload`
+pub struct FileCredentialStore {
+    path: PathBuf,
+    credentials: InMemoryCredentialStore,
+}
+
+impl FileCredentialStore {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, AuthError> {
+        let store = Self {
+            path: path.into(),
+            credentials: InMemoryCredentialStore::new(),
+        };
+        store.reload()?;
+        Ok(store)
+    }
+
+    pub fn reload(&self) -> Result<(), AuthError> {
+        let contents = std::fs::read_to_string(&self.path).map_err(|e| {
+            AuthError::CredentialStoreUnavailable(format!("{}: {}", self.path.display(), e))
+        })?;
+        let credentials: Vec<Credential> = serde_yaml::from_str(&contents).map_err(|e| {
+            AuthError::CredentialStoreUnavailable(format!("{}: {}", self.path.display(), e))
+        })?;
+
+        let mut current = self
+            .credentials
+            .credentials
+            .write()
+            .expect("credential store lock poisoned");
+        *current = credentials
+            .into_iter()
+            .map(|credential| (credential.username.clone(), credential))
+            .collect();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CredentialStore for FileCredentialStore {
+    async fn find(&self, username: &str) -> Result<Option<Credential>, AuthError> {
+        self.credentials.find(username).await
+    }
+}
@@ -0,0 +1,223 @@
+use crate::TokenType;
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("Invalid token")]
+    InvalidToken,
+    #[error("Malformed token")]
+    MalformedToken,
+    #[error("Invalid token signature")]
+    InvalidSignature,
+    #[error("Missing authorization header")]
+    MissingToken,
+    #[error("Invalid API key")]
+    InvalidApiKey,
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+    #[error("Token is not active")]
+    InactiveToken,
+    #[error("Token has been revoked")]
+    TokenRevoked,
+    #[error("Refresh token was already used")]
+    RefreshTokenReused,
+    #[error("Signed request body exceeds {0} bytes")]
+    PayloadTooLarge(usize),
+    #[error("Request signature timestamp is outside the allowed window")]
+    SignatureExpired,
+    #[error("Signed request was already used")]
+    ReplayedRequest,
+    #[error("Token introspection failed: {0}")]
+    IntrospectionUnavailable(String),
+    #[error("No API key store configured")]
+    ApiKeyStoreUnavailable,
+    #[error("Revocation store failed: {0}")]
+    RevocationStoreUnavailable(String),
+    #[error("Audit log unavailable: {0}")]
+    AuditLogUnavailable(String),
+    #[error("Credential store failed: {0}")]
+    CredentialStoreUnavailable(String),
+    #[error("Insufficient permissions")]
+    InsufficientPermissions,
+    #[error("Requires one of the roles: {}", .0.join(", "))]
+    InsufficientRole(Vec<String>),
+    #[error("Requires the scopes: {}", .0.join(" "))]
+    InsufficientScope(Vec<String>),
+    #[error("Requires the permissions: {}", .0.join(", "))]
+    PermissionDenied(Vec<String>),
+    #[error("Denied by the {0} policy")]
+    PolicyDenied(String),
+    #[error("Missing or mismatched CSRF token")]
+    CsrfMismatch,
+    #[error("Token does not belong to the request's tenant")]
+    TenantMismatch,
+    #[error("Unknown tenant: {0}")]
+    UnknownTenant(String),
+    #[error("No tenant for the request")]
+    MissingTenant,
+    #[error("Expected a {expected:?} token, got a {found:?} token")]
+    WrongTokenType {
+        expected: TokenType,
+        found: TokenType,
+    },
+    #[error("Token expired")]
+    TokenExpired,
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    #[error("No signing key configured")]
+    SigningKeyUnavailable,
+    #[error("Token lifetime out of range: {0} seconds")]
+    InvalidLifetime(i64),
+    #[error("Unknown or retired key: {0}")]
+    UnknownKey(String),
+    #[error("Failed to fetch keys: {0}")]
+    KeyProvider(String),
+    #[error("Invalid authorization policy: {0}")]
+    InvalidPolicy(String),
+    #[error("No authorization hook registered for {0}")]
+    UnknownPolicy(String),
+    #[error("Token not yet valid")]
+    TokenNotYetValid,
+    #[error("Token issued in the future")]
+    TokenIssuedInFuture,
+    #[error("Invalid token issuer")]
+    InvalidIssuer,
+    #[error("Invalid token audience")]
+    InvalidAudience,
+    #[error("Missing required claim: {0}")]
+    MissingClaim(String),
+}
+
+impl AuthError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::InsufficientPermissions
+            | AuthError::InsufficientRole(_)
+            | AuthError::InsufficientScope(_)
+            | AuthError::PermissionDenied(_)
+            | AuthError::PolicyDenied(_)
+            | AuthError::CsrfMismatch
+            | AuthError::TenantMismatch => StatusCode::FORBIDDEN,
+            AuthError::InvalidKey(_)
+            | AuthError::SigningKeyUnavailable
+            | AuthError::InvalidLifetime(_)
+            | AuthError::KeyProvider(_)
+            | AuthError::InvalidPolicy(_)
+            | AuthError::UnknownPolicy(_)
+            | AuthError::ApiKeyStoreUnavailable
+            | AuthError::RevocationStoreUnavailable(_)
+            | AuthError::AuditLogUnavailable(_)
+            | AuthError::CredentialStoreUnavailable(_)
+            | AuthError::IntrospectionUnavailable(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AuthError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    // Stable identifier for clients, sent as the problem's `code` member
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::InvalidToken => "invalid_token",
+            AuthError::MalformedToken => "malformed_token",
+            AuthError::InvalidSignature => "invalid_signature",
+            AuthError::MissingToken => "missing_token",
+            AuthError::InvalidApiKey => "invalid_api_key",
+            AuthError::InvalidCredentials => "invalid_credentials",
+            AuthError::InactiveToken => "inactive_token",
+            AuthError::TokenRevoked => "token_revoked",
+            AuthError::RefreshTokenReused => "refresh_token_reused",
+            AuthError::PayloadTooLarge(_) => "payload_too_large",
+            AuthError::SignatureExpired => "signature_expired",
+            AuthError::ReplayedRequest => "replayed_request",
+            AuthError::IntrospectionUnavailable(_) => "introspection_unavailable",
+            AuthError::ApiKeyStoreUnavailable => "api_key_store_unavailable",
+            AuthError::RevocationStoreUnavailable(_) => "revocation_store_unavailable",
+            AuthError::AuditLogUnavailable(_) => "audit_log_unavailable",
+            AuthError::CredentialStoreUnavailable(_) => "credential_store_unavailable",
+            AuthError::InsufficientPermissions => "insufficient_permissions",
+            AuthError::InsufficientRole(_) => "insufficient_role",
+            AuthError::InsufficientScope(_) => "insufficient_scope",
+            AuthError::PermissionDenied(_) => "permission_denied",
+            AuthError::PolicyDenied(_) => "policy_denied",
+            AuthError::CsrfMismatch => "csrf_mismatch",
+            AuthError::TenantMismatch => "tenant_mismatch",
+            AuthError::UnknownTenant(_) => "unknown_tenant",
+            AuthError::MissingTenant => "missing_tenant",
+            AuthError::WrongTokenType { .. } => "wrong_token_type",
+            AuthError::TokenExpired => "token_expired",
+            AuthError::InvalidKey(_) => "invalid_key",
+            AuthError::SigningKeyUnavailable => "signing_key_unavailable",
+            AuthError::InvalidLifetime(_) => "invalid_lifetime",
+            AuthError::UnknownKey(_) => "unknown_key",
+            AuthError::KeyProvider(_) => "key_provider_unavailable",
+            AuthError::InvalidPolicy(_) => "invalid_policy",
+            AuthError::UnknownPolicy(_) => "unknown_policy",
+            AuthError::TokenNotYetValid => "token_not_yet_valid",
+            AuthError::TokenIssuedInFuture => "token_issued_in_future",
+            AuthError::InvalidIssuer => "invalid_issuer",
+            AuthError::InvalidAudience => "invalid_audience",
+            AuthError::MissingClaim(_) => "missing_claim",
+        }
+    }
+
+    // RFC 6750 challenge; requests without credentials get no error code
+    fn www_authenticate(&self) -> Option<String> {
+        let description = self.to_string().replace(['"', '\\'], "'");
+        match self.status_code() {
+            StatusCode::UNAUTHORIZED if matches!(self, AuthError::MissingToken) => {
+                Some("Bearer".to_string())
+            }
+            // Not a token problem, so no challenge
+            _ if matches!(self, AuthError::CsrfMismatch) => None,
+            StatusCode::UNAUTHORIZED => Some(format!(
+                "Bearer error=\"invalid_token\", error_description=\"{}\"",
+                description
+            )),
+            StatusCode::FORBIDDEN => Some(format!(
+                "Bearer error=\"insufficient_scope\", error_description=\"{}\"",
+                description
+            )),
+            _ => None,
+        }
+    }
+}
+
+// RFC 7807 problem details with a Bearer challenge
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let detail = match status {
+            // Key and provider failures are not the client's concern
+            StatusCode::INTERNAL_SERVER_ERROR => "Authentication is unavailable".to_string(),
+            _ => self.to_string(),
+        };
+        let body = json!({
+            "type": "about:blank",
+            "title": status.canonical_reason().unwrap_or("Error"),
+            "status": status.as_u16(),
+            "detail": detail,
+            "code": self.code(),
+        });
+
+        let mut response = (status, Json(body)).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        if let Some(challenge) = self
+            .www_authenticate()
+            .and_then(|challenge| HeaderValue::from_str(&challenge).ok())
+        {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, challenge);
+        }
+        response
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{AuthClaims, AuthError, AuthService, MiddlewareFuture};
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Path, Request, State},
+    middleware::Next,
+};
+use std::collections::HashMap;
+
+// Per-operation authorization beyond roles, e.g. "users may only update their own tasks".
+// Registered on `AuthService` by name and referenced from routes with `require_policy`.
+#[async_trait]
+pub trait AuthorizationHook<C>: Send + Sync {
+    // `params` are the route's path parameters by name
+    async fn authorize(
+        &self,
+        claims: &C,
+        params: &HashMap<String, String>,
+    ) -> Result<bool, AuthError>;
+}
+
+// Authorization hook middleware factory; runs after authentication and answers 403 on denial
+pub fn require_policy<C: AuthClaims>(
+    name: &str,
+) -> impl Fn(State<AuthService<C>>, Request, Next) -> MiddlewareFuture + Clone {
+    let name = name.to_string();
+    move |State(auth_service): State<AuthService<C>>, request: Request, next: Next| {
+        let name = name.clone();
+        Box::pin(async move {
+            let hook = auth_service
+                .authorization_hook(&name)
+                .ok_or_else(|| AuthError::UnknownPolicy(name.clone()))?;
+
+            let (mut parts, body) = request.into_parts();
+            let params = Path::<HashMap<String, String>>::from_request_parts(&mut parts, &())
+                .await
+                .map(|Path(params)| params)
+                .unwrap_or_default();
+            let request = Request::from_parts(parts, body);
+
+            let claims = auth_service.request_claims(&request)?;
+            match hook.authorize(&claims, &params).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    let error = AuthError::PolicyDenied(name);
+                    return Err(auth_service.deny(&request, &claims, error));
+                }
+                Err(error) => return Err(auth_service.deny(&request, &claims, error)),
+            }
+
+            Ok(next.run(request).await)
+        })
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{AuthConfig, AuthError};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+// RFC 7662 client for opaque tokens, caching active responses until the token's `exp`
+pub struct TokenIntrospector {
+    url: String,
+    client: reqwest::Client,
+    client_credentials: Option<(String, String)>,
+    // Keyed by token hash so raw tokens are not kept in memory
+    cache: RwLock<HashMap<String, (Value, usize)>>,
+}
+
+impl TokenIntrospector {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::new(),
+            client_credentials: None,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // HTTP Basic credentials the resource server presents to the introspection endpoint
+    pub fn with_client_credentials(mut self, client_id: &str, client_secret: &str) -> Self {
+        self.client_credentials = Some((client_id.to_string(), client_secret.to_string()));
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Claims document for an active token, validated against `config`
+    pub async fn introspect(&self, token: &str, config: &AuthConfig) -> Result<Value, AuthError> {
+        let now = chrono::Utc::now().timestamp() as usize;
+        let cache_key = hex::encode(Sha256::digest(token.as_bytes()));
+
+        let cached = self
+            .cache
+            .read()
+            .expect("introspection cache lock poisoned")
+            .get(&cache_key)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(claims, _)| claims.clone());
+        if let Some(claims) = cached {
+            return Ok(claims);
+        }
+
+        let response = self.fetch(token).await?;
+        let expires_at = response.get("exp").and_then(Value::as_u64);
+        let claims = to_claims(response, config, now)?;
+        if let Some(exp) = expires_at {
+            let mut cache = self
+                .cache
+                .write()
+                .expect("introspection cache lock poisoned");
+            cache.retain(|_, (_, expires_at)| *expires_at > now);
+            cache.insert(cache_key, (claims.clone(), exp as usize));
+        }
+        Ok(claims)
+    }
+
+    async fn fetch(&self, token: &str) -> Result<Map<String, Value>, AuthError> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .form(&[("token", token), ("token_type_hint", "access_token")]);
+        if let Some((client_id, client_secret)) = &self.client_credentials {
+            request = request.basic_auth(client_id, Some(client_secret));
+        }
+
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AuthError::IntrospectionUnavailable(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| AuthError::IntrospectionUnavailable(e.to_string()))
+    }
+}
+
+// Maps an introspection response onto `Claims` fields: `scope` becomes roles unless
+// `roles` is present, and the subject falls back to `username` then `client_id`
+fn to_claims(
+    mut response: Map<String, Value>,
+    config: &AuthConfig,
+    now: usize,
+) -> Result<Value, AuthError> {
+    if response.remove("active") != Some(Value::Bool(true)) {
+        return Err(AuthError::InactiveToken);
+    }
+
+    let leeway = config.leeway_seconds as usize;
+    if let Some(exp) = response.get("exp").and_then(Value::as_u64)
+        && (exp as usize) + leeway < now
+    {
+        return Err(AuthError::TokenExpired);
+    }
+    if let Some(nbf) = response.get("nbf").and_then(Value::as_u64)
+        && nbf as usize > now + leeway
+    {
+        return Err(AuthError::TokenNotYetValid);
+    }
+    if let Some(issuer) = &config.issuer
+        && response.get("iss").and_then(Value::as_str) != Some(issuer.as_str())
+    {
+        return Err(AuthError::InvalidIssuer);
+    }
+    if !config.audiences.is_empty() {
+        let audiences: Vec<&str> = match response.get("aud") {
+            Some(Value::String(audience)) => vec![audience.as_str()],
+            Some(Value::Array(audiences)) => audiences.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !config
+            .audiences
+            .iter()
+            .any(|expected| audiences.contains(&expected.as_str()))
+        {
+            return Err(AuthError::InvalidAudience);
+        }
+    }
+
+    if !response.contains_key("sub") {
+        let subject = response
+            .get("username")
+            .or_else(|| response.get("client_id"))
+            .cloned()
+            .ok_or_else(|| AuthError::MissingClaim("sub".to_string()))?;
+        response.insert("sub".to_string(), subject);
+    }
+    if !response.contains_key("roles") {
+        let scopes: Vec<Value> = response
+            .get("scope")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|scope| Value::String(scope.to_string()))
+            .collect();
+        response.insert("roles".to_string(), Value::Array(scopes));
+    }
+    if !response.contains_key("type") {
+        response.insert("type".to_string(), Value::String("user".to_string()));
+    }
+    // `exp` is optional in introspection responses but required by `Claims`
+    response
+        .entry("exp")
+        .or_insert_with(|| Value::from(now + leeway));
+
+    Ok(Value::Object(response))
+}
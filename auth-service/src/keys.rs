@@ -0,0 +1,527 @@
+use crate::AuthError;
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+    EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType,
+    PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header};
+use simple_asn1::ASN1Block;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStatus {
+    // Signs new tokens and verifies existing ones
+    Active,
+    // Verifies existing tokens only
+    Verifying,
+    // Rejected, including when a refreshed JWKS still lists it
+    Retired,
+}
+
+struct ManagedKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    encoding_key: Option<EncodingKey>,
+    decoding_key: DecodingKey,
+    public_jwk: Option<Jwk>,
+    status: KeyStatus,
+    // Loaded from a `JwksProvider` and replaced on every refresh
+    remote: bool,
+}
+
+#[derive(Default)]
+struct KeySetInner {
+    keys: Vec<ManagedKey>,
+    retired: HashSet<String>,
+}
+
+// Signing and verification keys addressed by `kid`, shared between clones so that
+// rotation and background refreshes are visible to every `AuthService` using it
+#[derive(Clone, Default)]
+pub struct KeySet {
+    inner: Arc<RwLock<KeySetInner>>,
+}
+
+impl KeySet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Verification keys from a JWKS document; HMAC secrets are added with
+    // `add_signing_secret` instead
+    pub fn from_jwks_json(json: &str) -> Result<Self, AuthError> {
+        let jwks: JwkSet =
+            serde_json::from_str(json).map_err(|e| AuthError::InvalidKey(e.to_string()))?;
+        check_jwks(&jwks).map_err(AuthError::InvalidKey)?;
+        let key_set = Self::new();
+        for jwk in &jwks.keys {
+            key_set.add_jwk(jwk)?;
+        }
+        Ok(key_set)
+    }
+
+    pub fn from_jwks_file(path: impl Into<PathBuf>) -> Result<Self, AuthError> {
+        let path = path.into();
+        let json = std::fs::read_to_string(&path)
+            .map_err(|e| AuthError::InvalidKey(format!("{}: {}", path.display(), e)))?;
+        Self::from_jwks_json(&json)
+    }
+
+    // Key without a `kid`, used for tokens whose header carries none
+    pub(crate) fn single(
+        algorithm: Algorithm,
+        encoding_key: Option<EncodingKey>,
+        decoding_key: DecodingKey,
+    ) -> Self {
+        let key_set = Self::new();
+        key_set.insert_key(None, algorithm, encoding_key, decoding_key, None);
+        key_set
+    }
+
+    pub fn add_jwk(&self, jwk: &Jwk) -> Result<(), AuthError> {
+        let (kid, algorithm, decoding_key) = jwk_parts(jwk)?;
+        self.insert_key(Some(kid), algorithm, None, decoding_key, Some(jwk.clone()));
+        Ok(())
+    }
+
+    pub fn add_verification_key(&self, kid: &str, algorithm: Algorithm, decoding_key: DecodingKey) {
+        self.insert_key(Some(kid.to_string()), algorithm, None, decoding_key, None);
+    }
+
+    // Asymmetric signing key; its public half is published through `public_jwks`
+    pub fn add_signing_key_pem(
+        &self,
+        kid: &str,
+        algorithm: Algorithm,
+        private_key: &[u8],
+        public_key: &[u8],
+    ) -> Result<(), AuthError> {
+        let (encoding_key, decoding_key) = match algorithm {
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => (
+                EncodingKey::from_rsa_pem(private_key),
+                DecodingKey::from_rsa_pem(public_key),
+            ),
+            Algorithm::ES256 | Algorithm::ES384 => (
+                EncodingKey::from_ec_pem(private_key),
+                DecodingKey::from_ec_pem(public_key),
+            ),
+            Algorithm::EdDSA => (
+                EncodingKey::from_ed_pem(private_key),
+                DecodingKey::from_ed_pem(public_key),
+            ),
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                return Err(AuthError::InvalidKey(
+                    "HMAC secrets are added with add_signing_secret".to_string(),
+                ));
+            }
+        };
+        let encoding_key = encoding_key.map_err(|e| AuthError::InvalidKey(e.to_string()))?;
+        let decoding_key = decoding_key.map_err(|e| AuthError::InvalidKey(e.to_string()))?;
+        let public_jwk = public_jwk_from_pem(kid, algorithm, public_key)?;
+
+        self.insert_key(
+            Some(kid.to_string()),
+            algorithm,
+            Some(encoding_key),
+            decoding_key,
+            Some(public_jwk),
+        );
+        Ok(())
+    }
+
+    // Shared HMAC secret; never published
+    pub fn add_signing_secret(&self, kid: &str, algorithm: Algorithm, secret: &[u8]) {
+        self.insert_key(
+            Some(kid.to_string()),
+            algorithm,
+            Some(EncodingKey::from_secret(secret)),
+            DecodingKey::from_secret(secret),
+            None,
+        );
+    }
+
+    fn insert_key(
+        &self,
+        kid: Option<String>,
+        algorithm: Algorithm,
+        encoding_key: Option<EncodingKey>,
+        decoding_key: DecodingKey,
+        public_jwk: Option<Jwk>,
+    ) {
+        let mut inner = self.inner.write().expect("key set lock poisoned");
+        inner.keys.retain(|key| key.kid != kid);
+
+        let has_active = inner.keys.iter().any(|k| k.status == KeyStatus::Active);
+        let status = match &kid {
+            Some(kid) if inner.retired.contains(kid) => KeyStatus::Retired,
+            _ if encoding_key.is_some() && !has_active => KeyStatus::Active,
+            _ => KeyStatus::Verifying,
+        };
+
+        inner.keys.push(ManagedKey {
+            kid,
+            algorithm,
+            encoding_key,
+            decoding_key,
+            public_jwk,
+            status,
+            remote: false,
+        });
+    }
+
+    // Starts signing with `kid`; the previously active key keeps verifying
+    pub fn activate(&self, kid: &str) -> Result<(), AuthError> {
+        let mut inner = self.inner.write().expect("key set lock poisoned");
+        let can_activate = inner.keys.iter().any(|key| {
+            key.kid.as_deref() == Some(kid)
+                && key.encoding_key.is_some()
+                && key.status != KeyStatus::Retired
+        });
+        if !can_activate {
+            return Err(AuthError::UnknownKey(kid.to_string()));
+        }
+
+        for key in &mut inner.keys {
+            if key.kid.as_deref() == Some(kid) {
+                key.status = KeyStatus::Active;
+            } else if key.status == KeyStatus::Active {
+                key.status = KeyStatus::Verifying;
+            }
+        }
+        Ok(())
+    }
+
+    // Stops accepting tokens signed with `kid`
+    pub fn retire(&self, kid: &str) {
+        let mut inner = self.inner.write().expect("key set lock poisoned");
+        inner.retired.insert(kid.to_string());
+        for key in &mut inner.keys {
+            if key.kid.as_deref() == Some(kid) {
+                key.status = KeyStatus::Retired;
+            }
+        }
+    }
+
+    pub fn status(&self, kid: &str) -> Option<KeyStatus> {
+        let inner = self.inner.read().expect("key set lock poisoned");
+        inner
+            .keys
+            .iter()
+            .find(|key| key.kid.as_deref() == Some(kid))
+            .map(|key| key.status)
+    }
+
+    // Replaces previously fetched keys with `jwks`, leaving locally added keys untouched
+    pub fn replace_remote_keys(&self, jwks: &JwkSet) -> Result<(), AuthError> {
+        let fetched = jwks
+            .keys
+            .iter()
+            .map(|jwk| Ok((jwk_parts(jwk)?, jwk.clone())))
+            .collect::<Result<Vec<_>, AuthError>>()?;
+
+        let mut inner = self.inner.write().expect("key set lock poisoned");
+        inner.keys.retain(|key| !key.remote);
+        for ((kid, algorithm, decoding_key), jwk) in fetched {
+            if inner
+                .keys
+                .iter()
+                .any(|key| key.kid.as_deref() == Some(&kid))
+            {
+                continue;
+            }
+            let status = if inner.retired.contains(&kid) {
+                KeyStatus::Retired
+            } else {
+                KeyStatus::Verifying
+            };
+            inner.keys.push(ManagedKey {
+                kid: Some(kid),
+                algorithm,
+                encoding_key: None,
+                decoding_key,
+                public_jwk: Some(jwk),
+                status,
+                remote: true,
+            });
+        }
+        Ok(())
+    }
+
+    pub async fn refresh(&self, provider: &dyn JwksProvider) -> Result<(), AuthError> {
+        let jwks = provider.fetch().await?;
+        self.replace_remote_keys(&jwks)
+    }
+
+    // Refreshes from `provider` every `interval`; a failed fetch keeps the current keys
+    pub fn spawn_refresh(
+        &self,
+        provider: Arc<dyn JwksProvider>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let key_set = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(error) = key_set.refresh(provider.as_ref()).await {
+                    tracing::warn!(%error, "JWKS refresh failed, keeping the current keys");
+                }
+            }
+        })
+    }
+
+    // Public keys that verifiers should accept, for `/.well-known/jwks.json`
+    pub fn public_jwks(&self) -> JwkSet {
+        let inner = self.inner.read().expect("key set lock poisoned");
+        JwkSet {
+            keys: inner
+                .keys
+                .iter()
+                .filter(|key| key.status != KeyStatus::Retired && !key.remote)
+                .filter_map(|key| key.public_jwk.clone())
+                .collect(),
+        }
+    }
+
+    pub(crate) fn signing_key(&self) -> Option<(Header, EncodingKey)> {
+        let inner = self.inner.read().expect("key set lock poisoned");
+        let key = inner
+            .keys
+            .iter()
+            .find(|key| key.status == KeyStatus::Active)?;
+
+        let mut header = Header::new(key.algorithm);
+        header.kid = key.kid.clone();
+        key.encoding_key
+            .clone()
+            .map(|encoding_key| (header, encoding_key))
+    }
+
+    pub(crate) fn signing_algorithm(&self) -> Option<Algorithm> {
+        let inner = self.inner.read().expect("key set lock poisoned");
+        inner
+            .keys
+            .iter()
+            .find(|key| key.status == KeyStatus::Active)
+            .map(|key| key.algorithm)
+    }
+
+    pub(crate) fn set_signing_algorithm(&self, algorithm: Algorithm) {
+        let mut inner = self.inner.write().expect("key set lock poisoned");
+        if let Some(key) = inner
+            .keys
+            .iter_mut()
+            .find(|key| key.status == KeyStatus::Active)
+        {
+            key.algorithm = algorithm;
+        }
+    }
+
+    // Key for a token header's `kid`; tokens without one use the key without a `kid`
+    pub(crate) fn decoding_key(
+        &self,
+        kid: Option<&str>,
+    ) -> Result<(Algorithm, DecodingKey), AuthError> {
+        let inner = self.inner.read().expect("key set lock poisoned");
+        inner
+            .keys
+            .iter()
+            .filter(|key| key.status != KeyStatus::Retired)
+            .find(|key| key.kid.as_deref() == kid)
+            .map(|key| (key.algorithm, key.decoding_key.clone()))
+            .ok_or_else(|| AuthError::UnknownKey(kid.unwrap_or("<none>").to_string()))
+    }
+}
+
+// Source of verification keys, typically a remote issuer's JWKS endpoint
+#[async_trait]
+pub trait JwksProvider: Send + Sync {
+    async fn fetch(&self) -> Result<JwkSet, AuthError>;
+}
+
+pub struct FileJwksProvider {
+    path: PathBuf,
+}
+
+impl FileJwksProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl JwksProvider for FileJwksProvider {
+    async fn fetch(&self) -> Result<JwkSet, AuthError> {
+        let json = tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(|e| AuthError::KeyProvider(format!("{}: {}", self.path.display(), e)))?;
+        let jwks: JwkSet =
+            serde_json::from_str(&json).map_err(|e| AuthError::KeyProvider(e.to_string()))?;
+        check_jwks(&jwks).map_err(AuthError::KeyProvider)?;
+        Ok(jwks)
+    }
+}
+
+pub struct HttpJwksProvider {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpJwksProvider {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl JwksProvider for HttpJwksProvider {
+    async fn fetch(&self) -> Result<JwkSet, AuthError> {
+        let jwks = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AuthError::KeyProvider(e.to_string()))?
+            .json::<JwkSet>()
+            .await
+            .map_err(|e| AuthError::KeyProvider(e.to_string()))?;
+        check_jwks(&jwks).map_err(AuthError::KeyProvider)?;
+        Ok(jwks)
+    }
+}
+
+// Whoever serves or edits a JWKS, remote or on disk, must not be able to supply a shared
+// secret, nor leave the algorithm to be guessed from the key type
+fn check_jwks(jwks: &JwkSet) -> Result<(), String> {
+    for jwk in &jwks.keys {
+        let kid = jwk.common.key_id.as_deref().unwrap_or("<none>");
+        if matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)) {
+            return Err(format!(
+                "{}: symmetric keys are not accepted from a JWKS",
+                kid
+            ));
+        }
+        if jwk.common.key_algorithm.is_none() {
+            return Err(format!("{}: JWK without alg", kid));
+        }
+    }
+    Ok(())
+}
+
+fn jwk_parts(jwk: &Jwk) -> Result<(String, Algorithm, DecodingKey), AuthError> {
+    let kid = jwk
+        .common
+        .key_id
+        .clone()
+        .ok_or_else(|| AuthError::InvalidKey("JWK without kid".to_string()))?;
+
+    let algorithm = match (&jwk.common.key_algorithm, &jwk.algorithm) {
+        (Some(key_algorithm), _) => Algorithm::from_str(&key_algorithm.to_string())
+            .map_err(|e| AuthError::InvalidKey(e.to_string()))?,
+        (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+        (None, AlgorithmParameters::EllipticCurve(params)) => match params.curve {
+            EllipticCurve::P384 => Algorithm::ES384,
+            _ => Algorithm::ES256,
+        },
+        (None, AlgorithmParameters::OctetKeyPair(_)) => Algorithm::EdDSA,
+        (None, AlgorithmParameters::OctetKey(_)) => Algorithm::HS256,
+    };
+
+    let decoding_key =
+        DecodingKey::from_jwk(jwk).map_err(|e| AuthError::InvalidKey(e.to_string()))?;
+    Ok((kid, algorithm, decoding_key))
+}
+
+// Builds the public JWK for a PEM-encoded SubjectPublicKeyInfo (or PKCS#1 RSA) key
+fn public_jwk_from_pem(
+    kid: &str,
+    algorithm: Algorithm,
+    public_key: &[u8],
+) -> Result<Jwk, AuthError> {
+    let invalid = |reason: &str| AuthError::InvalidKey(format!("{}: {}", kid, reason));
+    let pem = pem::parse(public_key).map_err(|e| invalid(&e.to_string()))?;
+    let key_bits = match pem.tag() {
+        "RSA PUBLIC KEY" => pem.contents().to_vec(),
+        _ => subject_public_key(pem.contents()).ok_or_else(|| invalid("malformed public key"))?,
+    };
+
+    let parameters = match algorithm {
+        Algorithm::EdDSA => AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(&key_bits),
+        }),
+        Algorithm::ES256 | Algorithm::ES384 => {
+            // Uncompressed point: 0x04 || x || y
+            let point = key_bits
+                .strip_prefix(&[0x04])
+                .ok_or_else(|| invalid("compressed EC points are not supported"))?;
+            let (x, y) = point.split_at(point.len() / 2);
+            AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                key_type: EllipticCurveKeyType::EC,
+                curve: if algorithm == Algorithm::ES384 {
+                    EllipticCurve::P384
+                } else {
+                    EllipticCurve::P256
+                },
+                x: URL_SAFE_NO_PAD.encode(x),
+                y: URL_SAFE_NO_PAD.encode(y),
+            })
+        }
+        _ => {
+            let (n, e) = rsa_components(&key_bits).ok_or_else(|| invalid("malformed RSA key"))?;
+            AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(n),
+                e: URL_SAFE_NO_PAD.encode(e),
+            })
+        }
+    };
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: KeyAlgorithm::from_str(&format!("{:?}", algorithm)).ok(),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    })
+}
+
+fn subject_public_key(der: &[u8]) -> Option<Vec<u8>> {
+    match simple_asn1::from_der(der).ok()?.first()? {
+        ASN1Block::Sequence(_, blocks) => match blocks.get(1)? {
+            ASN1Block::BitString(_, _, bits) => Some(bits.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn rsa_components(der: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    match simple_asn1::from_der(der).ok()?.first()? {
+        ASN1Block::Sequence(_, blocks) => match blocks.as_slice() {
+            [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e), ..] => {
+                Some((n.to_bytes_be().1, e.to_bytes_be().1))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
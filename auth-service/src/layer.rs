@@ -0,0 +1,136 @@
+use crate::{AuthClaims, AuthError, AuthService, Claims, TokenType};
+use axum::{
+    BoxError,
+    body::{Body, Bytes, HttpBody},
+    http::{Request, Response},
+    response::IntoResponse,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+// Tower layer authenticating bearer tokens, independent of the router's state. Claims are
+// inserted into the request extensions for `AuthUser` or `request.extensions().get::<C>()`.
+#[derive(Clone)]
+pub struct AuthLayer<C = Claims> {
+    auth_service: AuthService<C>,
+    token_type: Option<TokenType>,
+    roles: Vec<String>,
+    optional: bool,
+}
+
+impl<C: AuthClaims> AuthLayer<C> {
+    pub fn user(auth_service: AuthService<C>) -> Self {
+        Self::new(auth_service, Some(TokenType::User))
+    }
+
+    pub fn system(auth_service: AuthService<C>) -> Self {
+        Self::new(auth_service, Some(TokenType::System))
+    }
+
+    // Accepts user and system tokens alike
+    pub fn any(auth_service: AuthService<C>) -> Self {
+        Self::new(auth_service, None)
+    }
+
+    fn new(auth_service: AuthService<C>, token_type: Option<TokenType>) -> Self {
+        Self {
+            auth_service,
+            token_type,
+            roles: Vec::new(),
+            optional: false,
+        }
+    }
+
+    // Requires at least one of the roles, like `require_roles`. A role requirement implies
+    // authentication, so it takes precedence over `optional`.
+    pub fn roles<I, S>(mut self, roles: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.roles = roles.into_iter().map(Into::into).collect();
+        self
+    }
+
+    // Lets requests without a token through unauthenticated; invalid tokens are still
+    // rejected. Ignored when `roles` are set, whichever is called first.
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    async fn authorize<B>(&self, request: &mut Request<B>) -> Result<(), AuthError> {
+        let result = match self
+            .auth_service
+            .authenticate_request(request, self.token_type)
+            .await
+        {
+            Err(AuthError::MissingToken) if self.optional && self.roles.is_empty() => {
+                return Ok(());
+            }
+            Ok(claims)
+                if !self.roles.is_empty()
+                    && !self.auth_service.has_any_role(&claims, &self.roles) =>
+            {
+                Err(AuthError::InsufficientRole(self.roles.clone()))
+            }
+            result => result,
+        };
+        self.auth_service.record_audit(request, &result);
+
+        crate::insert_claims(request, result?);
+        Ok(())
+    }
+}
+
+impl<S, C: Clone> Layer<S> for AuthLayer<C> {
+    type Service = AuthMiddleware<S, C>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthMiddleware {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthMiddleware<S, C = Claims> {
+    inner: S,
+    layer: AuthLayer<C>,
+}
+
+impl<S, C, ReqBody, ResBody> Service<Request<ReqBody>> for AuthMiddleware<S, C>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    C: AuthClaims,
+    ReqBody: Send + 'static,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        // The clone may not be ready, so keep the service `poll_ready` was called on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            if let Err(error) = layer.authorize(&mut request).await {
+                return Ok(error.into_response());
+            }
+            let response = inner.call(request).await?;
+            Ok(response.map(Body::new))
+        })
+    }
+}
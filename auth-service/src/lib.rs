@@ -1,25 +1,59 @@
 use axum::{
+    Json,
     extract::{Request, State},
-    http::{HeaderMap, StatusCode},
+    http::HeaderMap,
     middleware::Next,
     response::Response,
 };
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Validation, decode, decode_header, encode,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::marker::PhantomData;
 use std::sync::Arc;
-use thiserror::Error;
 
-#[derive(Error, Debug)]
-pub enum AuthError {
-    #[error("Invalid token")]
-    InvalidToken,
-    #[error("Missing authorization header")]
-    MissingToken,
-    #[error("Insufficient permissions")]
-    InsufficientPermissions,
-    #[error("Token expired")]
-    TokenExpired,
-}
+mod api_keys;
+mod audit;
+mod claims;
+mod credentials;
+mod error;
+mod hooks;
+mod introspection;
+mod keys;
+mod layer;
+mod policy;
+mod revocation;
+mod security;
+mod signing;
+mod tenancy;
+mod token_endpoint;
+
+pub use api_keys::{
+    ApiKeyRecord, ApiKeyStore, FileApiKeyStore, InMemoryApiKeyStore, hash_api_key, require_api_key,
+};
+pub use async_trait::async_trait;
+pub use audit::{AuditEvent, AuditOutcome, AuditSink, JsonLinesAuditSink, TracingAuditSink};
+pub use claims::{AuthClaims, CustomClaims};
+pub use credentials::{
+    Credential, CredentialStore, FileCredentialStore, InMemoryCredentialStore, hash_password,
+    verify_password,
+};
+pub use error::AuthError;
+pub use hooks::{AuthorizationHook, require_policy};
+pub use introspection::TokenIntrospector;
+pub use keys::{FileJwksProvider, HttpJwksProvider, JwksProvider, KeySet, KeyStatus};
+pub use layer::{AuthLayer, AuthMiddleware};
+pub use policy::{
+    AuthorizationPolicy, require_all_permissions, require_any_permission, require_permission,
+};
+pub use revocation::{FileRevocationStore, InMemoryRevocationStore, RevocationStore};
+use security::TokenSources;
+pub use security::{CredentialLocation, SchemeRequirement, SecurityPolicy, require_security};
+pub use signing::{RequestSigning, SigningKey, sign_request, signed_request_middleware};
+pub use tenancy::{TenantSource, Tenants};
+pub use token_endpoint::{TokenRequest, TokenResponse, token_endpoint};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
@@ -28,29 +62,265 @@ pub struct Claims {
     pub exp: usize,
     #[serde(rename = "type")]
     pub token_type: TokenType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "one_or_many"
+    )]
+    pub aud: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    // Refresh token family, shared by every token rotated from the same login
+    #[serde(default, rename = "fam", skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+// `aud` may be a single string or an array of strings
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(audience) => vec![audience],
+        OneOrMany::Many(audiences) => audiences,
+    })
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum TokenType {
     User,
     System,
+    // Only exchanged for new tokens, never accepted as an access token
+    Refresh,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+// Expected issuer and audiences, stamped on issued tokens and enforced on verification
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    pub issuer: Option<String>,
+    // Tokens must name at least one of these; tokens carrying `aud` are rejected when empty
+    pub audiences: Vec<String>,
+    // Clock skew tolerated for `exp`, `nbf` and `iat`
+    pub leeway_seconds: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            issuer: None,
+            audiences: Vec::new(),
+            leeway_seconds: 60,
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    pub fn with_audience(mut self, audience: impl Into<String>) -> Self {
+        self.audiences.push(audience.into());
+        self
+    }
+
+    pub fn with_leeway(mut self, leeway_seconds: u64) -> Self {
+        self.leeway_seconds = leeway_seconds;
+        self
+    }
 }
 
 #[derive(Clone)]
-pub struct AuthService {
-    encoding_key: Arc<EncodingKey>,
-    decoding_key: Arc<DecodingKey>,
+pub struct AuthService<C = Claims> {
+    keys: KeySet,
+    config: AuthConfig,
+    // Empty means each key only accepts its own algorithm
+    allowed_algorithms: Vec<Algorithm>,
+    api_keys: Option<Arc<dyn ApiKeyStore>>,
+    revocations: Option<Arc<dyn RevocationStore>>,
+    policy: Arc<AuthorizationPolicy>,
+    hooks: Arc<HashMap<String, Arc<dyn AuthorizationHook<C>>>>,
+    token_sources: TokenSources,
+    // Opaque-token mode: bearer tokens are introspected instead of verified locally
+    introspection: Option<Arc<TokenIntrospector>>,
+    request_signing: Option<Arc<RequestSigning>>,
+    audit: Option<Arc<dyn AuditSink>>,
+    tenants: Option<Arc<Tenants>>,
+    credentials: Option<Arc<dyn CredentialStore>>,
+    claims: PhantomData<fn() -> C>,
 }
 
 impl AuthService {
     pub fn new(secret: &[u8]) -> Self {
+        Self::from_keys(
+            Algorithm::HS256,
+            Some(EncodingKey::from_secret(secret)),
+            DecodingKey::from_secret(secret),
+        )
+    }
+
+    pub fn from_keys(
+        algorithm: Algorithm,
+        encoding_key: Option<EncodingKey>,
+        decoding_key: DecodingKey,
+    ) -> Self {
+        Self {
+            keys: KeySet::single(algorithm, encoding_key, decoding_key),
+            config: AuthConfig::default(),
+            allowed_algorithms: vec![algorithm],
+            api_keys: None,
+            revocations: None,
+            policy: Arc::default(),
+            hooks: Arc::default(),
+            token_sources: TokenSources::default(),
+            introspection: None,
+            request_signing: None,
+            audit: None,
+            tenants: None,
+            credentials: None,
+            claims: PhantomData,
+        }
+    }
+
+    // Signs with the set's active key and verifies tokens by their `kid`
+    pub fn from_key_set(keys: KeySet) -> Self {
         Self {
-            encoding_key: Arc::new(EncodingKey::from_secret(secret)),
-            decoding_key: Arc::new(DecodingKey::from_secret(secret)),
+            keys,
+            config: AuthConfig::default(),
+            allowed_algorithms: Vec::new(),
+            api_keys: None,
+            revocations: None,
+            policy: Arc::default(),
+            hooks: Arc::default(),
+            token_sources: TokenSources::default(),
+            introspection: None,
+            request_signing: None,
+            audit: None,
+            tenants: None,
+            credentials: None,
+            claims: PhantomData,
         }
     }
 
+    // RS256 from PKCS#1 or PKCS#8 private key and public key PEM
+    pub fn from_rsa_pem(private_key: &[u8], public_key: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self::from_keys(
+            Algorithm::RS256,
+            Some(EncodingKey::from_rsa_pem(private_key).map_err(invalid_key)?),
+            DecodingKey::from_rsa_pem(public_key).map_err(invalid_key)?,
+        ))
+    }
+
+    // ES256 from PKCS#8 private key and public key PEM
+    pub fn from_ec_pem(private_key: &[u8], public_key: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self::from_keys(
+            Algorithm::ES256,
+            Some(EncodingKey::from_ec_pem(private_key).map_err(invalid_key)?),
+            DecodingKey::from_ec_pem(public_key).map_err(invalid_key)?,
+        ))
+    }
+
+    // EdDSA from Ed25519 PKCS#8 private key and public key PEM
+    pub fn from_ed_pem(private_key: &[u8], public_key: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self::from_keys(
+            Algorithm::EdDSA,
+            Some(EncodingKey::from_ed_pem(private_key).map_err(invalid_key)?),
+            DecodingKey::from_ed_pem(public_key).map_err(invalid_key)?,
+        ))
+    }
+
+    pub fn from_rsa_der(private_key: &[u8], public_key: &[u8]) -> Self {
+        Self::from_keys(
+            Algorithm::RS256,
+            Some(EncodingKey::from_rsa_der(private_key)),
+            DecodingKey::from_rsa_der(public_key),
+        )
+    }
+
+    pub fn from_ec_der(private_key: &[u8], public_key: &[u8]) -> Self {
+        Self::from_keys(
+            Algorithm::ES256,
+            Some(EncodingKey::from_ec_der(private_key)),
+            DecodingKey::from_ec_der(public_key),
+        )
+    }
+
+    pub fn from_ed_der(private_key: &[u8], public_key: &[u8]) -> Self {
+        Self::from_keys(
+            Algorithm::EdDSA,
+            Some(EncodingKey::from_ed_der(private_key)),
+            DecodingKey::from_ed_der(public_key),
+        )
+    }
+
+    // Verify-only services: tokens can be checked but `create_token` fails
+    pub fn verifier_from_rsa_pem(public_key: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self::from_keys(
+            Algorithm::RS256,
+            None,
+            DecodingKey::from_rsa_pem(public_key).map_err(invalid_key)?,
+        ))
+    }
+
+    pub fn verifier_from_ec_pem(public_key: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self::from_keys(
+            Algorithm::ES256,
+            None,
+            DecodingKey::from_ec_pem(public_key).map_err(invalid_key)?,
+        ))
+    }
+
+    pub fn verifier_from_ed_pem(public_key: &[u8]) -> Result<Self, AuthError> {
+        Ok(Self::from_keys(
+            Algorithm::EdDSA,
+            None,
+            DecodingKey::from_ed_pem(public_key).map_err(invalid_key)?,
+        ))
+    }
+
+    pub fn verifier_from_rsa_der(public_key: &[u8]) -> Self {
+        Self::from_keys(
+            Algorithm::RS256,
+            None,
+            DecodingKey::from_rsa_der(public_key),
+        )
+    }
+
+    pub fn verifier_from_ec_der(public_key: &[u8]) -> Self {
+        Self::from_keys(Algorithm::ES256, None, DecodingKey::from_ec_der(public_key))
+    }
+
+    pub fn verifier_from_ed_der(public_key: &[u8]) -> Self {
+        Self::from_keys(Algorithm::EdDSA, None, DecodingKey::from_ed_der(public_key))
+    }
+
     pub fn create_token(
         &self,
         user_id: &str,
@@ -58,101 +328,695 @@ impl AuthService {
         token_type: TokenType,
         expires_in_hours: i64,
     ) -> Result<String, AuthError> {
-        let expiration = chrono::Utc::now()
+        let claims = self.standard_claims(user_id, roles, token_type, expires_in_hours);
+        self.sign(&claims)
+    }
+
+    // User access token plus a refresh token starting a new rotation family
+    pub fn create_token_pair(
+        &self,
+        user_id: &str,
+        roles: Vec<String>,
+        access_hours: i64,
+        refresh_hours: i64,
+    ) -> Result<TokenPair, AuthError> {
+        let access_token =
+            self.create_token(user_id, roles.clone(), TokenType::User, access_hours)?;
+        let mut refresh = self.standard_claims(user_id, roles, TokenType::Refresh, refresh_hours);
+        refresh.family = Some(uuid::Uuid::new_v4().to_string());
+
+        Ok(TokenPair {
+            access_token,
+            refresh_token: self.sign(&refresh)?,
+        })
+    }
+
+    // Exchanges a refresh token for a new pair. The presented token is used up and its
+    // successor keeps the family's absolute expiry; presenting a used token again is
+    // treated as theft and revokes the whole family.
+    pub async fn refresh(
+        &self,
+        refresh_token: &str,
+        access_hours: i64,
+    ) -> Result<TokenPair, AuthError> {
+        let store = self.revocation_store()?;
+        let claims: Claims = self.decode_claims(refresh_token, None)?;
+        if claims.token_type != TokenType::Refresh {
+            return Err(AuthError::WrongTokenType {
+                expected: TokenType::Refresh,
+                found: claims.token_type,
+            });
+        }
+        let jti = claims
+            .jti
+            .as_deref()
+            .ok_or_else(|| AuthError::MissingClaim("jti".to_string()))?;
+        let family = claims
+            .family
+            .as_deref()
+            .ok_or_else(|| AuthError::MissingClaim("fam".to_string()))?;
+
+        if store.is_revoked(family).await? {
+            return Err(AuthError::TokenRevoked);
+        }
+        if !store.revoke(jti, claims.exp).await? {
+            store.revoke(family, claims.exp).await?;
+            return Err(AuthError::RefreshTokenReused);
+        }
+
+        let access_token = self.create_token(
+            &claims.sub,
+            claims.roles.clone(),
+            TokenType::User,
+            access_hours,
+        )?;
+        let mut successor = self.standard_claims(&claims.sub, claims.roles, TokenType::Refresh, 0);
+        successor.exp = claims.exp;
+        successor.family = claims.family;
+
+        Ok(TokenPair {
+            access_token,
+            refresh_token: self.sign(&successor)?,
+        })
+    }
+}
+
+impl<C: AuthClaims> AuthService<C> {
+    // Verifies tokens carrying `D` instead, e.g. `CustomClaims<T>`
+    pub fn with_claims<D: AuthClaims>(self) -> AuthService<D> {
+        AuthService {
+            keys: self.keys,
+            config: self.config,
+            allowed_algorithms: self.allowed_algorithms,
+            api_keys: self.api_keys,
+            revocations: self.revocations,
+            policy: self.policy,
+            // Hooks are typed by the claims they receive, so they are registered afterwards
+            hooks: Arc::default(),
+            token_sources: self.token_sources,
+            introspection: self.introspection,
+            request_signing: self.request_signing,
+            audit: self.audit,
+            tenants: self.tenants,
+            credentials: self.credentials,
+            claims: PhantomData,
+        }
+    }
+
+    pub fn key_set(&self) -> &KeySet {
+        &self.keys
+    }
+
+    // Signing algorithm within the key's family, e.g. RS512 or PS256 for an RSA key
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.keys.set_signing_algorithm(algorithm);
+        if !self.allowed_algorithms.contains(&algorithm) {
+            self.allowed_algorithms.push(algorithm);
+        }
+        self
+    }
+
+    // Algorithms accepted when verifying; must share the decoding key's family
+    pub fn with_allowed_algorithms(mut self, algorithms: Vec<Algorithm>) -> Self {
+        self.allowed_algorithms = algorithms;
+        self
+    }
+
+    pub fn with_config(mut self, config: AuthConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &AuthConfig {
+        &self.config
+    }
+
+    // Enables API keys; `CredentialLocation` schemes other than bearer are then looked up here
+    pub fn with_api_key_store(mut self, store: Arc<dyn ApiKeyStore>) -> Self {
+        self.api_keys = Some(store);
+        self
+    }
+
+    pub fn has_api_key_store(&self) -> bool {
+        self.api_keys.is_some()
+    }
+
+    // Role inheritance and permissions used by the role and permission checks
+    pub fn with_policy(mut self, policy: AuthorizationPolicy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    pub fn policy(&self) -> &AuthorizationPolicy {
+        &self.policy
+    }
+
+    // Hook referenced by `require_policy(name)` and the `x-auth-policy` extension
+    pub fn with_authorization_hook(
+        mut self,
+        name: &str,
+        hook: impl AuthorizationHook<C> + 'static,
+    ) -> Self {
+        Arc::make_mut(&mut self.hooks).insert(name.to_string(), Arc::new(hook));
+        self
+    }
+
+    pub fn authorization_hook(&self, name: &str) -> Option<Arc<dyn AuthorizationHook<C>>> {
+        self.hooks.get(name).cloned()
+    }
+
+    pub fn with_revocation_store(mut self, store: Arc<dyn RevocationStore>) -> Self {
+        self.revocations = Some(store);
+        self
+    }
+
+    fn revocation_store(&self) -> Result<&Arc<dyn RevocationStore>, AuthError> {
+        self.revocations.as_ref().ok_or_else(|| {
+            AuthError::RevocationStoreUnavailable("no revocation store configured".to_string())
+        })
+    }
+
+    // Rejects the token until it expires; revoking a refresh token ends its whole family
+    pub async fn revoke_token(&self, token: &str) -> Result<(), AuthError> {
+        let store = self.revocation_store()?;
+        let claims: Claims = self.decode_claims(token, None)?;
+        let jti = claims
+            .jti
+            .as_deref()
+            .ok_or_else(|| AuthError::MissingClaim("jti".to_string()))?;
+
+        store.revoke(jti, claims.exp).await?;
+        if let Some(family) = &claims.family {
+            store.revoke(family, claims.exp).await?;
+        }
+        Ok(())
+    }
+
+    // Where the middlewares look for tokens, first match wins; defaults to the
+    // `Authorization: Bearer` header. Cookie tokens require the CSRF double-submit on
+    // unsafe methods.
+    pub fn with_token_sources(mut self, sources: Vec<CredentialLocation>) -> Self {
+        self.token_sources.locations = sources;
+        self
+    }
+
+    // Defaults to the `csrf_token` cookie echoed in `X-CSRF-Token`
+    pub fn with_csrf_names(mut self, cookie: &str, header: &str) -> Self {
+        self.token_sources.csrf_cookie = cookie.to_string();
+        self.token_sources.csrf_header = header.to_ascii_lowercase();
+        self
+    }
+
+    // Token from the first configured source that carries one
+    pub fn token_from_request<B>(
+        &self,
+        request: &axum::http::Request<B>,
+    ) -> Result<String, AuthError> {
+        self.token_sources
+            .extract(request.method(), request.headers(), request.uri().query())
+    }
+
+    pub fn with_introspection(mut self, introspector: TokenIntrospector) -> Self {
+        self.introspection = Some(Arc::new(introspector));
+        self
+    }
+
+    // Shared keys for `signed_request_middleware`
+    pub fn with_request_signing(mut self, signing: RequestSigning) -> Self {
+        self.request_signing = Some(Arc::new(signing));
+        self
+    }
+
+    pub(crate) fn request_signing(&self) -> Option<&RequestSigning> {
+        self.request_signing.as_deref()
+    }
+
+    // Records every authentication attempt made by the middlewares and `AuthLayer`, and every
+    // request refused by the role, permission and policy middlewares or the token endpoint
+    pub fn with_audit_sink(mut self, sink: impl AuditSink + 'static) -> Self {
+        self.audit = Some(Arc::new(sink));
+        self
+    }
+
+    pub(crate) fn record_audit<B>(
+        &self,
+        request: &axum::http::Request<B>,
+        result: &Result<C, AuthError>,
+    ) {
+        if let Some(sink) = &self.audit {
+            sink.record(&audit::AuditEvent::new(request, result));
+        }
+    }
+
+    // Records the refusal of an authenticated request, returning the error to respond with
+    pub(crate) fn deny<B>(
+        &self,
+        request: &axum::http::Request<B>,
+        claims: &C,
+        error: AuthError,
+    ) -> AuthError {
+        if let Some(sink) = &self.audit {
+            sink.record(&audit::AuditEvent::denied(request, claims, &error));
+        }
+        error
+    }
+
+    // Claims attached by a preceding auth middleware; a request without them is refused
+    pub(crate) fn request_claims<B>(
+        &self,
+        request: &axum::http::Request<B>,
+    ) -> Result<C, AuthError> {
+        let result = request
+            .extensions()
+            .get::<C>()
+            .cloned()
+            .ok_or(AuthError::MissingToken);
+        if result.is_err() {
+            self.record_audit(request, &result);
+        }
+        result
+    }
+
+    // Authenticates the request's token, requiring `expected` as its type when given. The
+    // future does not borrow the request, which may not be `Sync`.
+    pub(crate) fn authenticate_request<B>(
+        &self,
+        request: &axum::http::Request<B>,
+        expected: Option<TokenType>,
+    ) -> impl std::future::Future<Output = Result<C, AuthError>> + Send + use<'_, B, C> {
+        let token = self.token_from_request(request);
+        let key_tenant = self
+            .tenants
+            .as_ref()
+            .and_then(|tenants| tenants.key_tenant(request));
+        let request_tenant = self.request_tenant(request);
+        async move {
+            let claims = self
+                .authenticate_with(&token?, key_tenant.as_deref())
+                .await?;
+
+            if let Some(expected) = expected
+                && claims.token_type() != expected
+            {
+                return Err(AuthError::WrongTokenType {
+                    expected,
+                    found: claims.token_type(),
+                });
+            }
+            check_tenant(&claims, request_tenant.as_deref())?;
+            Ok(claims)
+        }
+    }
+
+    pub fn with_tenants(mut self, tenants: Tenants) -> Self {
+        self.tenants = Some(Arc::new(tenants));
+        self
+    }
+
+    // Tenant the request addresses under the tenants' scope
+    pub(crate) fn request_tenant<B>(&self, request: &axum::http::Request<B>) -> Option<String> {
+        self.tenants
+            .as_ref()
+            .and_then(|tenants| tenants.request_tenant(request))
+    }
+
+    // Keys of the token's tenant; tokens without one, such as platform-wide system tokens,
+    // use the service's own keys
+    fn tenant_keys(&self, tenant: Option<&str>) -> Result<&KeySet, AuthError> {
+        match (&self.tenants, tenant) {
+            (Some(tenants), Some(tenant)) => tenants.keys(tenant),
+            _ => Ok(&self.keys),
+        }
+    }
+
+    fn tenant_policy(&self, claims: &C) -> &AuthorizationPolicy {
+        self.tenants
+            .as_ref()
+            .zip(claims.tenant_id())
+            .and_then(|(tenants, tenant)| tenants.policy(tenant))
+            .unwrap_or(&self.policy)
+    }
+
+    // Claims for a bearer token, from the introspection endpoint when configured
+    pub async fn authenticate(&self, token: &str) -> Result<C, AuthError> {
+        self.authenticate_with(token, None).await
+    }
+
+    async fn authenticate_with(
+        &self,
+        token: &str,
+        key_tenant: Option<&str>,
+    ) -> Result<C, AuthError> {
+        match &self.introspection {
+            Some(introspector) => {
+                let claims = introspector.introspect(token, &self.config).await?;
+                serde_json::from_value(claims).map_err(|_| AuthError::InvalidToken)
+            }
+            None => {
+                let claims = self.verify_token_with(token, key_tenant)?;
+                self.check_revoked(&claims).await?;
+                Ok(claims)
+            }
+        }
+    }
+
+    async fn check_revoked(&self, claims: &C) -> Result<(), AuthError> {
+        if let (Some(store), Some(jti)) = (&self.revocations, claims.token_id())
+            && store.is_revoked(jti).await?
+        {
+            return Err(AuthError::TokenRevoked);
+        }
+        Ok(())
+    }
+
+    // Users and clients the token endpoint issues tokens to
+    pub fn with_credential_store(mut self, store: impl CredentialStore + 'static) -> Self {
+        self.credentials = Some(Arc::new(store));
+        self
+    }
+
+    // Credential whose password matches, among users or, for `TokenType::System`, clients
+    pub async fn verify_credentials(
+        &self,
+        username: &str,
+        password: &str,
+        token_type: TokenType,
+    ) -> Result<Credential, AuthError> {
+        let store = self.credentials.as_ref().ok_or_else(|| {
+            AuthError::CredentialStoreUnavailable("no credential store configured".to_string())
+        })?;
+        let credential = store
+            .find(username)
+            .await?
+            .filter(|credential| credential.token_type == token_type);
+
+        // Argon2 is deliberately slow, so keep it off the async workers
+        let password = password.to_string();
+        let (credential, valid) = tokio::task::spawn_blocking(move || {
+            let valid = Credential::verify(credential.as_ref(), &password);
+            (credential, valid)
+        })
+        .await
+        .map_err(|e| AuthError::CredentialStoreUnavailable(e.to_string()))?;
+
+        credential
+            .filter(|_| valid)
+            .ok_or(AuthError::InvalidCredentials)
+    }
+
+    // Token for a verified credential, carrying its extra claims
+    pub fn issue_token(
+        &self,
+        credential: &Credential,
+        expires_in_seconds: i64,
+    ) -> Result<String, AuthError> {
+        let mut claims = self.standard_claims(
+            &credential.username,
+            credential.roles.clone(),
+            credential.token_type,
+            0,
+        );
+        claims.exp = Some(expires_in_seconds)
+            .filter(|seconds| *seconds > 0)
+            .and_then(|seconds| chrono::Utc::now().timestamp().checked_add(seconds))
+            .and_then(|exp| usize::try_from(exp).ok())
+            .ok_or(AuthError::InvalidLifetime(expires_in_seconds))?;
+
+        let serde_json::Value::Object(mut document) =
+            serde_json::to_value(&claims).map_err(|_| AuthError::InvalidToken)?
+        else {
+            unreachable!("claims serialize to an object");
+        };
+        for (name, value) in &credential.extra {
+            document
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+        let claims: C = serde_json::from_value(serde_json::Value::Object(document))
+            .map_err(|_| AuthError::InvalidToken)?;
+        self.sign(&claims)
+    }
+
+    pub async fn verify_api_key(&self, key: &str) -> Result<C, AuthError> {
+        let store = self
+            .api_keys
+            .as_ref()
+            .ok_or(AuthError::ApiKeyStoreUnavailable)?;
+        let record = store
+            .find(&hash_api_key(key))
+            .await?
+            .ok_or(AuthError::InvalidApiKey)?;
+
+        if record
+            .expires_at
+            .is_some_and(|expires_at| expires_at < chrono::Utc::now().timestamp() as usize)
+        {
+            return Err(AuthError::TokenExpired);
+        }
+
+        serde_json::from_value(record.claims()).map_err(|_| AuthError::InvalidApiKey)
+    }
+
+    pub fn algorithm(&self) -> Option<Algorithm> {
+        self.keys.signing_algorithm()
+    }
+
+    pub fn can_sign(&self) -> bool {
+        self.keys.signing_key().is_some()
+    }
+
+    fn validation(&self, key_algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(key_algorithm);
+        if !self.allowed_algorithms.is_empty() {
+            validation.algorithms = self.allowed_algorithms.clone();
+        }
+        validation.leeway = self.config.leeway_seconds;
+        validation.validate_nbf = true;
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+            validation.required_spec_claims.insert("iss".to_string());
+        }
+        if !self.config.audiences.is_empty() {
+            validation.set_audience(&self.config.audiences);
+            validation.required_spec_claims.insert("aud".to_string());
+        }
+        validation
+    }
+
+    // Registered claims for a new token, stamped with the configured issuer and audiences
+    pub fn standard_claims(
+        &self,
+        user_id: &str,
+        roles: Vec<String>,
+        token_type: TokenType,
+        expires_in_hours: i64,
+    ) -> Claims {
+        let now = chrono::Utc::now();
+        let expiration = now
             .checked_add_signed(chrono::Duration::hours(expires_in_hours))
             .expect("valid timestamp")
             .timestamp() as usize;
+        let issued_at = now.timestamp() as usize;
 
-        let claims = Claims {
+        Claims {
             sub: user_id.to_owned(),
             roles,
             exp: expiration,
             token_type,
-        };
+            iss: self.config.issuer.clone(),
+            aud: self.config.audiences.clone(),
+            iat: Some(issued_at),
+            nbf: Some(issued_at),
+            jti: Some(uuid::Uuid::new_v4().to_string()),
+            family: None,
+            tenant: None,
+        }
+    }
+
+    pub fn sign(&self, claims: &C) -> Result<String, AuthError> {
+        let (header, encoding_key) = self
+            .tenant_keys(claims.tenant_id())?
+            .signing_key()
+            .ok_or(AuthError::SigningKeyUnavailable)?;
+
+        encode(&header, claims, &encoding_key).map_err(|_| AuthError::InvalidToken)
+    }
+
+    // Signature, time and claim checks only; `authenticate` also consults the revocation store
+    pub fn verify_token(&self, token: &str) -> Result<C, AuthError> {
+        self.verify_token_with(token, None)
+    }
 
-        encode(&Header::default(), &claims, &self.encoding_key).map_err(|_| AuthError::InvalidToken)
+    // `key_tenant` names the tenant whose keys verify the token instead of its claim
+    fn verify_token_with(&self, token: &str, key_tenant: Option<&str>) -> Result<C, AuthError> {
+        let claims: C = self.decode_claims(token, key_tenant)?;
+        if claims.token_type() == TokenType::Refresh {
+            return Err(AuthError::WrongTokenType {
+                expected: TokenType::User,
+                found: TokenType::Refresh,
+            });
+        }
+        Ok(claims)
     }
 
-    pub fn verify_token(&self, token: &str) -> Result<Claims, AuthError> {
-        decode::<Claims>(token, &self.decoding_key, &Validation::default())
+    // Signature and time checks shared by access and refresh tokens
+    fn decode_claims<T: AuthClaims>(
+        &self,
+        token: &str,
+        key_tenant: Option<&str>,
+    ) -> Result<T, AuthError> {
+        let header = decode_header(token).map_err(|_| AuthError::MalformedToken)?;
+        // The claimed tenant only picks the keys; verifying with them proves the claim
+        let tenant = match &self.tenants {
+            Some(_) => key_tenant.map(str::to_string).or_else(|| {
+                inspect_token(token)
+                    .ok()
+                    .and_then(|(_, claims)| claims["tenant"].as_str().map(str::to_string))
+            }),
+            None => None,
+        };
+        let (algorithm, decoding_key) = self
+            .tenant_keys(tenant.as_deref())?
+            .decoding_key(header.kid.as_deref())?;
+        let mut validation = self.validation(algorithm);
+        if tenant.is_some() {
+            // Each tenant's keys decide its algorithms, not the service-wide list
+            validation.algorithms = vec![algorithm];
+        }
+
+        let claims = decode::<T>(token, &decoding_key, &validation)
             .map(|data| data.claims)
-            .map_err(|_| AuthError::InvalidToken)
+            .map_err(validation_error)?;
+        if tenant.is_some() && claims.tenant_id() != tenant.as_deref() {
+            return Err(AuthError::TenantMismatch);
+        }
+
+        let latest_iat = chrono::Utc::now().timestamp() as u64 + self.config.leeway_seconds;
+        if claims
+            .issued_at()
+            .is_some_and(|iat| iat as u64 > latest_iat)
+        {
+            return Err(AuthError::TokenIssuedInFuture);
+        }
+
+        Ok(claims)
     }
 
-    pub fn has_role(&self, claims: &Claims, required_role: &str) -> bool {
-        claims.roles.iter().any(|r| r == required_role)
+    // Inherited roles count, e.g. `superadmin` has `admin` when the policy says so
+    pub fn has_role(&self, claims: &C, required_role: &str) -> bool {
+        self.tenant_policy(claims)
+            .effective_roles(claims.roles())
+            .contains(required_role)
     }
 
-    pub fn has_any_role(&self, claims: &Claims, required_roles: &[String]) -> bool {
+    pub fn has_any_role(&self, claims: &C, required_roles: &[String]) -> bool {
+        let roles = self.tenant_policy(claims).effective_roles(claims.roles());
         required_roles
             .iter()
-            .any(|required| claims.roles.iter().any(|r| r == required))
+            .any(|required| roles.contains(required.as_str()))
+    }
+
+    pub fn has_permission(&self, claims: &C, permission: &str) -> bool {
+        self.tenant_policy(claims)
+            .effective_permissions(claims.roles())
+            .contains(permission)
+    }
+
+    pub fn has_all_permissions(&self, claims: &C, permissions: &[String]) -> bool {
+        let granted = self
+            .tenant_policy(claims)
+            .effective_permissions(claims.roles());
+        permissions
+            .iter()
+            .all(|permission| granted.contains(permission.as_str()))
+    }
+
+    pub fn has_any_permission(&self, claims: &C, permissions: &[String]) -> bool {
+        let granted = self
+            .tenant_policy(claims)
+            .effective_permissions(claims.roles());
+        permissions
+            .iter()
+            .any(|permission| granted.contains(permission.as_str()))
     }
 }
 
 // Middleware for user authentication
-pub async fn user_auth_middleware(
-    State(auth_service): State<AuthService>,
-    headers: HeaderMap,
+pub async fn user_auth_middleware<C: AuthClaims>(
+    State(auth_service): State<AuthService<C>>,
     mut request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    let token = extract_token(&headers)?;
+) -> Result<Response, AuthError> {
+    let result = auth_service
+        .authenticate_request(&request, Some(TokenType::User))
+        .await;
+    auth_service.record_audit(&request, &result);
 
-    let claims = auth_service
-        .verify_token(&token)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    insert_claims(&mut request, result?);
+    Ok(next.run(request).await)
+}
 
-    if claims.token_type != TokenType::User {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+// Middleware for system-to-system authentication
+pub async fn system_auth_middleware<C: AuthClaims>(
+    State(auth_service): State<AuthService<C>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let result = auth_service
+        .authenticate_request(&request, Some(TokenType::System))
+        .await;
+    auth_service.record_audit(&request, &result);
 
-    request.extensions_mut().insert(claims);
+    insert_claims(&mut request, result?);
     Ok(next.run(request).await)
 }
 
-// Middleware for system-to-system authentication
-pub async fn system_auth_middleware(
-    State(auth_service): State<AuthService>,
-    headers: HeaderMap,
+// Middleware for endpoints shared by users and other services
+pub async fn any_auth_middleware<C: AuthClaims>(
+    State(auth_service): State<AuthService<C>>,
     mut request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
-    let token = extract_token(&headers)?;
+) -> Result<Response, AuthError> {
+    let result = auth_service.authenticate_request(&request, None).await;
+    auth_service.record_audit(&request, &result);
 
-    let claims = auth_service
-        .verify_token(&token)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    insert_claims(&mut request, result?);
+    Ok(next.run(request).await)
+}
 
-    if claims.token_type != TokenType::System {
-        return Err(StatusCode::UNAUTHORIZED);
+// Middleware for endpoints open to anonymous requests that still read claims when a token
+// is sent; a token that fails verification is rejected rather than ignored
+pub async fn optional_auth_middleware<C: AuthClaims>(
+    State(auth_service): State<AuthService<C>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    // A CSRF failure still rejects; only the absence of a token is anonymous
+    match auth_service.authenticate_request(&request, None).await {
+        Err(AuthError::MissingToken) => {}
+        result => {
+            auth_service.record_audit(&request, &result);
+            insert_claims(&mut request, result?);
+        }
     }
 
-    request.extensions_mut().insert(claims);
     Ok(next.run(request).await)
 }
 
+type MiddlewareFuture =
+    std::pin::Pin<Box<dyn std::future::Future<Output = Result<Response, AuthError>> + Send>>;
+
 // Role-based authorization middleware factory
-pub fn require_roles(
+pub fn require_roles<C: AuthClaims>(
     required_roles: Vec<String>,
-) -> impl Fn(
-    State<AuthService>,
-    Request,
-    Next,
-)
-    -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Response, StatusCode>> + Send>>
-+ Clone {
-    move |State(auth_service): State<AuthService>, request: Request, next: Next| {
+) -> impl Fn(State<AuthService<C>>, Request, Next) -> MiddlewareFuture + Clone {
+    move |State(auth_service): State<AuthService<C>>, request: Request, next: Next| {
         let required_roles = required_roles.clone();
         Box::pin(async move {
-            let claims = request
-                .extensions()
-                .get::<Claims>()
-                .ok_or(StatusCode::UNAUTHORIZED)?
-                .clone();
-
+            let claims = auth_service.request_claims(&request)?;
             if !auth_service.has_any_role(&claims, &required_roles) {
-                return Err(StatusCode::FORBIDDEN);
+                let error = AuthError::InsufficientRole(required_roles);
+                return Err(auth_service.deny(&request, &claims, error));
             }
 
             Ok(next.run(request).await)
@@ -160,27 +1024,129 @@ pub fn require_roles(
     }
 }
 
-fn extract_token(headers: &HeaderMap) -> Result<String, StatusCode> {
-    let auth_header = headers
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok())
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+// Serves the public signing keys, mounted at `/.well-known/jwks.json`
+pub async fn jwks_handler<C: AuthClaims>(
+    State(auth_service): State<AuthService<C>>,
+) -> Json<JwkSet> {
+    Json(auth_service.key_set().public_jwks())
+}
 
-    if !auth_header.starts_with("Bearer ") {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+// Header and claims of a token without checking its signature or validity, for debugging
+// tools; never authorize anything based on the result
+pub fn inspect_token(token: &str) -> Result<(jsonwebtoken::Header, serde_json::Value), AuthError> {
+    use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 
-    Ok(auth_header.trim_start_matches("Bearer ").to_string())
+    let header = decode_header(token).map_err(|_| AuthError::MalformedToken)?;
+    let claims = token
+        .split('.')
+        .nth(1)
+        .and_then(|payload| URL_SAFE_NO_PAD.decode(payload).ok())
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or(AuthError::MalformedToken)?;
+    Ok((header, claims))
 }
 
-// Helper struct for extracting claims in handlers
-pub struct AuthUser(pub Claims);
+fn invalid_key(error: jsonwebtoken::errors::Error) -> AuthError {
+    AuthError::InvalidKey(error.to_string())
+}
 
-impl<S> axum::extract::FromRequestParts<S> for AuthUser
-where
+fn validation_error(error: jsonwebtoken::errors::Error) -> AuthError {
+    use jsonwebtoken::errors::ErrorKind;
+
+    match error.into_kind() {
+        ErrorKind::ExpiredSignature => AuthError::TokenExpired,
+        ErrorKind::ImmatureSignature => AuthError::TokenNotYetValid,
+        ErrorKind::InvalidIssuer => AuthError::InvalidIssuer,
+        ErrorKind::InvalidAudience => AuthError::InvalidAudience,
+        ErrorKind::MissingRequiredClaim(claim) => AuthError::MissingClaim(claim),
+        ErrorKind::InvalidSignature => AuthError::InvalidSignature,
+        ErrorKind::InvalidToken
+        | ErrorKind::Base64(_)
+        | ErrorKind::Json(_)
+        | ErrorKind::Utf8(_) => AuthError::MalformedToken,
+        _ => AuthError::InvalidToken,
+    }
+}
+
+fn extract_token(headers: &HeaderMap) -> Result<String, AuthError> {
+    let auth_header = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or(AuthError::MissingToken)?;
+
+    // The auth scheme is case-insensitive (RFC 7235)
+    match auth_header.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => {
+            Ok(token.trim().to_string())
+        }
+        _ => Err(AuthError::MissingToken),
+    }
+}
+
+// Token tenant must match the tenant the request addresses, when it names one
+pub(crate) fn check_tenant<C: AuthClaims>(
+    claims: &C,
+    request_tenant: Option<&str>,
+) -> Result<(), AuthError> {
+    match request_tenant {
+        Some(tenant) if claims.tenant_id() != Some(tenant) => Err(AuthError::TenantMismatch),
+        _ => Ok(()),
+    }
+}
+
+// Attaches authenticated claims, and their tenant for the `Tenant` extractor
+pub(crate) fn insert_claims<B, C: AuthClaims>(request: &mut axum::http::Request<B>, claims: C) {
+    if let Some(tenant) = claims.tenant_id() {
+        request.extensions_mut().insert(Tenant(tenant.to_string()));
+    }
+    request.extensions_mut().insert(claims);
+}
+
+// Tenant of the authenticated token, for multi-tenant services
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tenant(pub String);
+
+impl<S> axum::extract::FromRequestParts<S> for Tenant
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Tenant>()
+            .cloned()
+            .ok_or(AuthError::MissingTenant)
+    }
+}
+
+impl<S> axum::extract::OptionalFromRequestParts<S> for Tenant
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<Tenant>().cloned())
+    }
+}
+
+// Helper struct for extracting claims in handlers
+pub struct AuthUser<C = Claims>(pub C);
+
+impl<S, C> axum::extract::FromRequestParts<S> for AuthUser<C>
+where
     S: Send + Sync,
+    C: AuthClaims,
 {
-    type Rejection = StatusCode;
+    type Rejection = AuthError;
 
     async fn from_request_parts(
         parts: &mut axum::http::request::Parts,
@@ -188,16 +1154,51 @@ where
     ) -> Result<Self, Self::Rejection> {
         parts
             .extensions
-            .get::<Claims>()
+            .get::<C>()
             .cloned()
             .map(AuthUser)
-            .ok_or(StatusCode::UNAUTHORIZED)
+            .ok_or(AuthError::MissingToken)
+    }
+}
+
+// `Option<AuthUser>` is `None` for anonymous requests
+impl<S, C> axum::extract::OptionalFromRequestParts<S> for AuthUser<C>
+where
+    S: Send + Sync,
+    C: AuthClaims,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<C>().cloned().map(AuthUser))
+    }
+}
+
+// Claims if the request was authenticated, for routes behind `optional_auth_middleware`
+pub struct MaybeAuthUser<C = Claims>(pub Option<C>);
+
+impl<S, C> axum::extract::FromRequestParts<S> for MaybeAuthUser<C>
+where
+    S: Send + Sync,
+    C: AuthClaims,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(MaybeAuthUser(parts.extensions.get::<C>().cloned()))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum::http::Method;
 
     #[test]
     fn test_create_and_verify_user_token() {
@@ -229,6 +1230,1161 @@ mod tests {
         assert_eq!(claims.token_type, TokenType::System);
     }
 
+    #[test]
+    fn test_asymmetric_algorithms() {
+        let services = [
+            AuthService::from_rsa_pem(
+                include_bytes!("../testdata/rsa_private.pem"),
+                include_bytes!("../testdata/rsa_public.pem"),
+            )
+            .unwrap(),
+            AuthService::from_ec_pem(
+                include_bytes!("../testdata/ec_private.pem"),
+                include_bytes!("../testdata/ec_public.pem"),
+            )
+            .unwrap(),
+            AuthService::from_ed_pem(
+                include_bytes!("../testdata/ed25519_private.pem"),
+                include_bytes!("../testdata/ed25519_public.pem"),
+            )
+            .unwrap(),
+        ];
+
+        for auth_service in services {
+            let token = auth_service
+                .create_token("user123", vec!["user".to_string()], TokenType::User, 1)
+                .unwrap();
+            let claims = auth_service.verify_token(&token).unwrap();
+            assert_eq!(claims.sub, "user123");
+        }
+    }
+
+    #[test]
+    fn test_verify_only_service() {
+        let issuer = AuthService::from_ed_pem(
+            include_bytes!("../testdata/ed25519_private.pem"),
+            include_bytes!("../testdata/ed25519_public.pem"),
+        )
+        .unwrap();
+        let verifier =
+            AuthService::verifier_from_ed_pem(include_bytes!("../testdata/ed25519_public.pem"))
+                .unwrap();
+
+        let token = issuer
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        assert_eq!(verifier.verify_token(&token).unwrap().sub, "user123");
+        assert!(matches!(
+            verifier.create_token("user123", vec![], TokenType::User, 1),
+            Err(AuthError::SigningKeyUnavailable)
+        ));
+    }
+
+    #[test]
+    fn test_disallowed_algorithm_is_rejected() {
+        let hs256 = AuthService::new(b"test_secret");
+        let hs512 = AuthService::new(b"test_secret").with_algorithm(Algorithm::HS512);
+
+        let token = hs512
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        assert!(hs512.verify_token(&token).is_ok());
+        assert!(hs256.verify_token(&token).is_err());
+    }
+
+    #[test]
+    fn test_key_rotation_by_kid() {
+        let keys = KeySet::new();
+        keys.add_signing_key_pem(
+            "ed-1",
+            Algorithm::EdDSA,
+            include_bytes!("../testdata/ed25519_private.pem"),
+            include_bytes!("../testdata/ed25519_public.pem"),
+        )
+        .unwrap();
+        keys.add_signing_key_pem(
+            "ec-2",
+            Algorithm::ES256,
+            include_bytes!("../testdata/ec_private.pem"),
+            include_bytes!("../testdata/ec_public.pem"),
+        )
+        .unwrap();
+        let auth_service = AuthService::from_key_set(keys.clone());
+
+        let old_token = auth_service
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        assert_eq!(
+            decode_header(&old_token).unwrap().kid.as_deref(),
+            Some("ed-1")
+        );
+
+        keys.activate("ec-2").unwrap();
+        assert_eq!(keys.status("ed-1"), Some(KeyStatus::Verifying));
+        let new_token = auth_service
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        assert_eq!(auth_service.algorithm(), Some(Algorithm::ES256));
+        assert!(auth_service.verify_token(&old_token).is_ok());
+        assert!(auth_service.verify_token(&new_token).is_ok());
+        assert_eq!(keys.public_jwks().keys.len(), 2);
+
+        keys.retire("ed-1");
+        assert!(matches!(
+            auth_service.verify_token(&old_token),
+            Err(AuthError::UnknownKey(_))
+        ));
+        assert!(auth_service.verify_token(&new_token).is_ok());
+        assert_eq!(keys.public_jwks().keys.len(), 1);
+    }
+
+    #[test]
+    fn test_published_jwks_verifies_tokens() {
+        let issuer_keys = KeySet::new();
+        issuer_keys
+            .add_signing_key_pem(
+                "rsa-1",
+                Algorithm::RS256,
+                include_bytes!("../testdata/rsa_private.pem"),
+                include_bytes!("../testdata/rsa_public.pem"),
+            )
+            .unwrap();
+        let issuer = AuthService::from_key_set(issuer_keys.clone());
+
+        let jwks = serde_json::to_string(&issuer_keys.public_jwks()).unwrap();
+        let verifier = AuthService::from_key_set(KeySet::from_jwks_json(&jwks).unwrap());
+        assert!(!verifier.can_sign());
+
+        let token = issuer
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        assert_eq!(verifier.verify_token(&token).unwrap().sub, "user123");
+    }
+
+    #[tokio::test]
+    async fn test_jwks_sources_reject_secrets_and_missing_alg() {
+        let symmetric =
+            r#"{"keys": [{"kty": "oct", "kid": "hs", "alg": "HS256", "k": "c2VjcmV0"}]}"#;
+        let mut rsa = serde_json::to_value({
+            let keys = KeySet::new();
+            keys.add_signing_key_pem(
+                "rsa-1",
+                Algorithm::RS256,
+                include_bytes!("../testdata/rsa_private.pem"),
+                include_bytes!("../testdata/rsa_public.pem"),
+            )
+            .unwrap();
+            keys.public_jwks()
+        })
+        .unwrap();
+        rsa["keys"][0].as_object_mut().unwrap().remove("alg");
+        let without_alg = rsa.to_string();
+
+        for jwks in [symmetric, without_alg.as_str()] {
+            assert!(matches!(
+                KeySet::from_jwks_json(jwks),
+                Err(AuthError::InvalidKey(_))
+            ));
+
+            let dir = std::env::temp_dir().join(format!("jwks-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir(&dir).unwrap();
+            std::fs::write(dir.join("keys.json"), jwks).unwrap();
+            std::fs::write(
+                dir.join("tenants.yaml"),
+                "tenants:\n  acme:\n    jwks: keys.json\n",
+            )
+            .unwrap();
+            let provider = FileJwksProvider::new(dir.join("keys.json"));
+            assert!(matches!(
+                provider.fetch().await,
+                Err(AuthError::KeyProvider(_))
+            ));
+            assert!(Tenants::from_file(dir.join("tenants.yaml")).is_err());
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_from_jwks_endpoint() {
+        let issuer_keys = KeySet::new();
+        issuer_keys
+            .add_signing_key_pem(
+                "ec-1",
+                Algorithm::ES256,
+                include_bytes!("../testdata/ec_private.pem"),
+                include_bytes!("../testdata/ec_public.pem"),
+            )
+            .unwrap();
+        let issuer = AuthService::from_key_set(issuer_keys);
+
+        let app = axum::Router::new()
+            .route("/.well-known/jwks.json", axum::routing::get(jwks_handler))
+            .route(
+                "/symmetric.json",
+                axum::routing::get(|| async {
+                    axum::Json(serde_json::json!({
+                        "keys": [{"kty": "oct", "kid": "hs", "alg": "HS256", "k": "c2VjcmV0"}]
+                    }))
+                }),
+            )
+            .with_state(issuer.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let verifier = AuthService::from_key_set(KeySet::new());
+        let token = issuer
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        assert!(verifier.verify_token(&token).is_err());
+
+        let provider = HttpJwksProvider::new(format!("http://{}/.well-known/jwks.json", addr));
+        verifier.key_set().refresh(&provider).await.unwrap();
+        assert_eq!(verifier.verify_token(&token).unwrap().sub, "user123");
+
+        // Retired keys stay rejected even though the endpoint still lists them
+        verifier.key_set().retire("ec-1");
+        verifier.key_set().refresh(&provider).await.unwrap();
+        assert!(verifier.verify_token(&token).is_err());
+
+        // A remote JWKS cannot hand out shared secrets
+        let symmetric = HttpJwksProvider::new(format!("http://{}/symmetric.json", addr));
+        assert!(matches!(
+            verifier.key_set().refresh(&symmetric).await,
+            Err(AuthError::KeyProvider(_))
+        ));
+        assert!(verifier.key_set().status("hs").is_none());
+    }
+
+    #[test]
+    fn test_issuer_and_audience_validation() {
+        let config = AuthConfig::new()
+            .with_issuer("https://auth.example.com")
+            .with_audience("orders");
+        let orders = AuthService::new(b"test_secret").with_config(config.clone());
+        let billing = AuthService::new(b"test_secret").with_config(
+            AuthConfig::new()
+                .with_issuer("https://auth.example.com")
+                .with_audience("billing"),
+        );
+        let other_issuer = AuthService::new(b"test_secret").with_config(
+            AuthConfig::new()
+                .with_issuer("https://evil.example.com")
+                .with_audience("orders"),
+        );
+        let unconfigured = AuthService::new(b"test_secret");
+
+        let token = orders
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        let claims = orders.verify_token(&token).unwrap();
+        assert_eq!(claims.iss.as_deref(), Some("https://auth.example.com"));
+        assert_eq!(claims.aud, vec!["orders".to_string()]);
+        assert!(claims.iat.is_some() && claims.nbf.is_some() && claims.jti.is_some());
+
+        assert!(matches!(
+            billing.verify_token(&token),
+            Err(AuthError::InvalidAudience)
+        ));
+        assert!(matches!(
+            other_issuer.verify_token(&token),
+            Err(AuthError::InvalidIssuer)
+        ));
+        assert!(matches!(
+            unconfigured.verify_token(&token),
+            Err(AuthError::InvalidAudience)
+        ));
+
+        let untargeted = unconfigured
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        assert!(matches!(
+            orders.verify_token(&untargeted),
+            Err(AuthError::MissingClaim(_))
+        ));
+    }
+
+    #[test]
+    fn test_time_claims_respect_leeway() {
+        let now = chrono::Utc::now().timestamp() as usize;
+        let claims = |iat: usize, nbf: usize, exp: usize| Claims {
+            sub: "user123".to_string(),
+            roles: vec![],
+            exp,
+            token_type: TokenType::User,
+            iss: None,
+            aud: Vec::new(),
+            iat: Some(iat),
+            nbf: Some(nbf),
+            jti: None,
+            family: None,
+            tenant: None,
+        };
+        let sign = |claims: &Claims| {
+            encode(
+                &jsonwebtoken::Header::default(),
+                claims,
+                &EncodingKey::from_secret(b"test_secret"),
+            )
+            .unwrap()
+        };
+        let strict = AuthService::new(b"test_secret").with_config(AuthConfig::new().with_leeway(0));
+        let lenient =
+            AuthService::new(b"test_secret").with_config(AuthConfig::new().with_leeway(120));
+
+        let not_yet_valid = sign(&claims(now, now + 60, now + 3600));
+        assert!(matches!(
+            strict.verify_token(&not_yet_valid),
+            Err(AuthError::TokenNotYetValid)
+        ));
+        assert!(lenient.verify_token(&not_yet_valid).is_ok());
+
+        let expired = sign(&claims(now - 3600, now - 3600, now - 60));
+        assert!(matches!(
+            strict.verify_token(&expired),
+            Err(AuthError::TokenExpired)
+        ));
+        assert!(lenient.verify_token(&expired).is_ok());
+
+        let from_the_future = sign(&claims(now + 600, now, now + 3600));
+        assert!(matches!(
+            lenient.verify_token(&from_the_future),
+            Err(AuthError::TokenIssuedInFuture)
+        ));
+    }
+
+    #[test]
+    fn test_verification_errors_are_distinguished() {
+        let auth_service = AuthService::new(b"test_secret");
+        let token = auth_service
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+
+        let other_secret = AuthService::new(b"other_secret");
+        assert!(matches!(
+            other_secret.verify_token(&token),
+            Err(AuthError::InvalidSignature)
+        ));
+        assert!(matches!(
+            auth_service.verify_token("not-a-jwt"),
+            Err(AuthError::MalformedToken)
+        ));
+
+        let expired = auth_service
+            .create_token("user123", vec![], TokenType::User, -2)
+            .unwrap();
+        assert!(matches!(
+            auth_service.verify_token(&expired),
+            Err(AuthError::TokenExpired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_middleware_error_responses() {
+        let auth_service = AuthService::new(b"test_secret");
+        let app = axum::Router::new()
+            .route("/admin", axum::routing::get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(
+                auth_service.clone(),
+                require_roles(vec!["admin".to_string()]),
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                auth_service.clone(),
+                user_auth_middleware,
+            ))
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/admin", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let request = |token: Option<String>| {
+            let request = client.get(&url);
+            match token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+        };
+
+        let response = request(None).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer");
+
+        let expired = auth_service
+            .create_token("user123", vec![], TokenType::User, -2)
+            .unwrap();
+        let response = request(Some(expired)).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/problem+json"
+        );
+        assert!(
+            response.headers()["www-authenticate"]
+                .to_str()
+                .unwrap()
+                .starts_with("Bearer error=\"invalid_token\"")
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["status"], 401);
+        assert_eq!(problem["code"], "token_expired");
+
+        let system = auth_service
+            .create_token("service-a", vec![], TokenType::System, 1)
+            .unwrap();
+        let problem: serde_json::Value = request(Some(system))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(problem["code"], "wrong_token_type");
+
+        let user = auth_service
+            .create_token("user123", vec!["user".to_string()], TokenType::User, 1)
+            .unwrap();
+        let response = request(Some(user)).send().await.unwrap();
+        assert_eq!(response.status(), 403);
+        assert!(
+            response.headers()["www-authenticate"]
+                .to_str()
+                .unwrap()
+                .starts_with("Bearer error=\"insufficient_scope\"")
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["code"], "insufficient_role");
+
+        let admin = auth_service
+            .create_token("user123", vec!["admin".to_string()], TokenType::User, 1)
+            .unwrap();
+        let response = request(Some(admin)).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    #[test]
+    fn test_custom_claims() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TenantClaims {
+            tenant_id: String,
+            email: String,
+            features: Vec<String>,
+        }
+
+        let auth_service =
+            AuthService::new(b"test_secret").with_claims::<CustomClaims<TenantClaims>>();
+        let claims = CustomClaims::new(
+            auth_service.standard_claims("user123", vec!["admin".to_string()], TokenType::User, 1),
+            TenantClaims {
+                tenant_id: "acme".to_string(),
+                email: "user@acme.test".to_string(),
+                features: vec!["beta".to_string()],
+            },
+        );
+        let token = auth_service.sign(&claims).unwrap();
+
+        let verified = auth_service.verify_token(&token).unwrap();
+        assert_eq!(verified.subject(), "user123");
+        assert_eq!(verified.tenant_id, "acme");
+        assert_eq!(verified.features, vec!["beta".to_string()]);
+        assert!(auth_service.has_role(&verified, "admin"));
+
+        // Services using the default claims ignore the extra fields
+        let plain = AuthService::new(b"test_secret");
+        assert_eq!(plain.verify_token(&token).unwrap().sub, "user123");
+        let missing_fields = plain
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        assert!(auth_service.verify_token(&missing_fields).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_type_middlewares() {
+        let auth_service = AuthService::new(b"test_secret");
+        let app = axum::Router::new()
+            .route(
+                "/jobs",
+                axum::routing::get(|| async { "ok" }).layer(axum::middleware::from_fn_with_state(
+                    auth_service.clone(),
+                    system_auth_middleware,
+                )),
+            )
+            .route(
+                "/status",
+                axum::routing::get(|| async { "ok" }).layer(axum::middleware::from_fn_with_state(
+                    auth_service.clone(),
+                    any_auth_middleware,
+                )),
+            )
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let user = auth_service
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        let system = auth_service
+            .create_token("service-a", vec![], TokenType::System, 1)
+            .unwrap();
+        let client = reqwest::Client::new();
+        let status = |path: &str, token: &str| {
+            client
+                .get(format!("{}{}", base, path))
+                .bearer_auth(token)
+                .send()
+        };
+
+        assert_eq!(status("/jobs", &system).await.unwrap().status(), 200);
+        assert_eq!(status("/jobs", &user).await.unwrap().status(), 401);
+        assert_eq!(status("/status", &system).await.unwrap().status(), 200);
+        assert_eq!(status("/status", &user).await.unwrap().status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_security_policy_alternatives() {
+        let auth_service = AuthService::new(b"test_secret");
+        let reader = auth_service
+            .create_token("user123", vec!["jobs:read".to_string()], TokenType::User, 1)
+            .unwrap();
+        let plain = auth_service
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        let headers = |pairs: &[(&'static str, String)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+
+        // oauth with a scope OR (api key in a cookie AND bearer)
+        let policy = SecurityPolicy::new()
+            .or(vec![SchemeRequirement::bearer(vec![
+                "jobs:read".to_string(),
+            ])])
+            .or(vec![
+                SchemeRequirement::api_key(
+                    CredentialLocation::Cookie("api_key".to_string()),
+                    vec![],
+                ),
+                SchemeRequirement::bearer(vec![]),
+            ]);
+
+        let scoped = headers(&[("authorization", format!("Bearer {}", reader))]);
+        assert!(
+            policy
+                .authorize(&auth_service, &Method::GET, &scoped, None)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        let unscoped = headers(&[("authorization", format!("Bearer {}", plain))]);
+        assert!(matches!(
+            policy
+                .authorize(&auth_service, &Method::GET, &unscoped, None)
+                .await,
+            Err(AuthError::InsufficientScope(_))
+        ));
+
+        let both = headers(&[
+            ("authorization", format!("Bearer {}", plain)),
+            ("cookie", format!("theme=dark; api_key={}", plain)),
+        ]);
+        assert!(
+            policy
+                .authorize(&auth_service, &Method::GET, &both, None)
+                .await
+                .is_ok()
+        );
+
+        // Browsers send cookies on their own, so unsafe methods need the CSRF double-submit
+        let cookie_policy = SecurityPolicy::new().or(vec![SchemeRequirement::api_key(
+            CredentialLocation::Cookie("api_key".to_string()),
+            vec![],
+        )]);
+        let cookie = headers(&[("cookie", format!("api_key={}; csrf_token=abc", plain))]);
+        assert!(matches!(
+            cookie_policy
+                .authorize(&auth_service, &Method::POST, &cookie, None)
+                .await,
+            Err(AuthError::CsrfMismatch)
+        ));
+        let submitted = headers(&[
+            ("cookie", format!("api_key={}; csrf_token=abc", plain)),
+            ("x-csrf-token", "abc".to_string()),
+        ]);
+        assert!(
+            cookie_policy
+                .authorize(&auth_service, &Method::POST, &submitted, None)
+                .await
+                .is_ok()
+        );
+
+        let query_policy = SecurityPolicy::new().or(vec![SchemeRequirement::api_key(
+            CredentialLocation::Query("api_key".to_string()),
+            vec![],
+        )]);
+        let query = format!("page=2&api_key={}", plain);
+        assert!(
+            query_policy
+                .authorize(&auth_service, &Method::GET, &HeaderMap::new(), Some(&query))
+                .await
+                .is_ok()
+        );
+
+        let anonymous = policy.clone().allow_anonymous();
+        assert!(
+            anonymous
+                .authorize(&auth_service, &Method::GET, &HeaderMap::new(), None)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            anonymous
+                .authorize(&auth_service, &Method::GET, &scoped, None)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        // Like `optional_auth_middleware`, an invalid token is rejected rather than anonymous
+        let invalid = headers(&[("authorization", "Bearer garbage".to_string())]);
+        assert!(matches!(
+            anonymous
+                .authorize(&auth_service, &Method::GET, &invalid, None)
+                .await,
+            Err(AuthError::MalformedToken)
+        ));
+
+        assert!(matches!(
+            SecurityPolicy::new()
+                .authorize(&auth_service, &Method::GET, &scoped, None)
+                .await,
+            Err(AuthError::MissingToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_token_introspection() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let exp = chrono::Utc::now().timestamp() + 3600;
+        let introspect = {
+            let calls = calls.clone();
+            move |body: String| async move {
+                calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let response = if body.contains("token=opaque-active") {
+                    serde_json::json!({
+                        "active": true,
+                        "username": "user123",
+                        "scope": "read write",
+                        "exp": exp,
+                    })
+                } else {
+                    serde_json::json!({ "active": false })
+                };
+                Json(response)
+            }
+        };
+        let issuer = axum::Router::new().route("/introspect", axum::routing::post(introspect));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let introspection_url = format!("http://{}/introspect", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, issuer).await.unwrap() });
+
+        let auth_service = AuthService::new(b"test_secret")
+            .with_introspection(TokenIntrospector::new(introspection_url));
+
+        let claims = auth_service.authenticate("opaque-active").await.unwrap();
+        assert_eq!(claims.sub, "user123");
+        assert_eq!(claims.roles, vec!["read", "write"]);
+        assert_eq!(claims.token_type, TokenType::User);
+        assert_eq!(claims.exp, exp as usize);
+
+        // Active responses are cached until the token expires
+        auth_service.authenticate("opaque-active").await.unwrap();
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        assert!(matches!(
+            auth_service.authenticate("opaque-revoked").await,
+            Err(AuthError::InactiveToken)
+        ));
+
+        let app = axum::Router::new()
+            .route(
+                "/profile",
+                axum::routing::get(|AuthUser(claims): AuthUser| async move { claims.sub }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                auth_service.clone(),
+                user_auth_middleware,
+            ))
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/profile", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let response = client
+            .get(&url)
+            .bearer_auth("opaque-active")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "user123");
+
+        let response = client
+            .get(&url)
+            .bearer_auth("opaque-revoked")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "inactive_token");
+
+        // An unreachable endpoint is a server-side failure, not a client one
+        let unreachable = AuthService::new(b"test_secret")
+            .with_introspection(TokenIntrospector::new("http://127.0.0.1:1/introspect"));
+        assert!(matches!(
+            unreachable.authenticate("opaque-active").await,
+            Err(AuthError::IntrospectionUnavailable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_rotation() {
+        let auth_service = AuthService::new(b"test_secret")
+            .with_revocation_store(Arc::new(InMemoryRevocationStore::new()));
+        let pair = auth_service
+            .create_token_pair("user123", vec!["user".to_string()], 1, 24)
+            .unwrap();
+        assert_eq!(
+            auth_service.verify_token(&pair.access_token).unwrap().sub,
+            "user123"
+        );
+        assert!(matches!(
+            auth_service.verify_token(&pair.refresh_token),
+            Err(AuthError::WrongTokenType {
+                found: TokenType::Refresh,
+                ..
+            })
+        ));
+
+        let rotated = auth_service.refresh(&pair.refresh_token, 1).await.unwrap();
+        let claims = auth_service.verify_token(&rotated.access_token).unwrap();
+        assert_eq!(claims.roles, vec!["user"]);
+        assert!(matches!(
+            auth_service.refresh(&rotated.access_token, 1).await,
+            Err(AuthError::WrongTokenType {
+                expected: TokenType::Refresh,
+                ..
+            })
+        ));
+
+        // Replaying a used refresh token revokes every token rotated from it
+        assert!(matches!(
+            auth_service.refresh(&pair.refresh_token, 1).await,
+            Err(AuthError::RefreshTokenReused)
+        ));
+        assert!(matches!(
+            auth_service.refresh(&rotated.refresh_token, 1).await,
+            Err(AuthError::TokenRevoked)
+        ));
+
+        let unconfigured = AuthService::new(b"test_secret");
+        assert!(matches!(
+            unconfigured.refresh(&rotated.refresh_token, 1).await,
+            Err(AuthError::RevocationStoreUnavailable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_token_revocation() {
+        let path = std::env::temp_dir().join(format!("revoked-{}.json", uuid::Uuid::new_v4()));
+        let auth_service = AuthService::new(b"test_secret")
+            .with_revocation_store(Arc::new(FileRevocationStore::open(&path).unwrap()));
+        let token = auth_service
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+        let other = auth_service
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+
+        auth_service.revoke_token(&token).await.unwrap();
+        assert!(matches!(
+            auth_service.authenticate(&token).await,
+            Err(AuthError::TokenRevoked)
+        ));
+        assert!(auth_service.authenticate(&other).await.is_ok());
+
+        // The list survives a restart
+        let restarted = AuthService::new(b"test_secret")
+            .with_revocation_store(Arc::new(FileRevocationStore::open(&path).unwrap()));
+        assert!(matches!(
+            restarted.authenticate(&token).await,
+            Err(AuthError::TokenRevoked)
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        // Concurrent revocations all reach the file
+        let store = Arc::new(FileRevocationStore::open(&path).unwrap());
+        let exp = chrono::Utc::now().timestamp() as usize + 3600;
+        let revocations: Vec<_> = (0..20)
+            .map(|i| {
+                let store = store.clone();
+                tokio::spawn(async move { store.revoke(&format!("jti-{}", i), exp).await })
+            })
+            .collect();
+        for revocation in revocations {
+            assert!(revocation.await.unwrap().unwrap());
+        }
+        let reopened = FileRevocationStore::open(&path).unwrap();
+        for i in 0..20 {
+            assert!(reopened.is_revoked(&format!("jti-{}", i)).await.unwrap());
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let app = axum::Router::new()
+            .route("/profile", axum::routing::get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(
+                restarted.clone(),
+                user_auth_middleware,
+            ))
+            .with_state(restarted);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/profile", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let response = client.get(&url).bearer_auth(&token).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "token_revoked");
+        let response = client.get(&url).bearer_auth(&other).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_auth_layer() {
+        use tower::ServiceExt;
+
+        let auth_service = AuthService::new(b"test_secret");
+        let user_token = auth_service
+            .create_token("user123", vec!["admin".to_string()], TokenType::User, 1)
+            .unwrap();
+        let system_token = auth_service
+            .create_token("billing", vec![], TokenType::System, 1)
+            .unwrap();
+
+        // The router's own state is unrelated to the auth service
+        #[derive(Clone)]
+        struct AppState {
+            greeting: &'static str,
+        }
+        let app = axum::Router::new()
+            .route(
+                "/admin",
+                axum::routing::get(
+                    |State(state): State<AppState>, AuthUser(claims): AuthUser| async move {
+                        format!("{} {}", state.greeting, claims.sub)
+                    },
+                )
+                .layer(AuthLayer::user(auth_service.clone()).roles(["admin"])),
+            )
+            .route(
+                "/feed",
+                axum::routing::get(|claims: Option<axum::Extension<Claims>>| async move {
+                    claims.map_or("anonymous".to_string(), |claims| claims.sub.clone())
+                })
+                .layer(AuthLayer::any(auth_service.clone()).optional()),
+            )
+            .route(
+                "/reports",
+                axum::routing::get(|| async { "reports" }).layer(
+                    AuthLayer::any(auth_service.clone())
+                        .optional()
+                        .roles(["admin"]),
+                ),
+            )
+            .with_state(AppState { greeting: "hello" });
+        let request = |uri: &str, token: Option<&str>| {
+            let mut request = axum::http::Request::get(uri);
+            if let Some(token) = token {
+                request = request.header("Authorization", format!("Bearer {}", token));
+            }
+            request.body(axum::body::Body::empty()).unwrap()
+        };
+        let send = |request: axum::http::Request<axum::body::Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        assert_eq!(
+            send(request("/admin", Some(&user_token))).await,
+            (axum::http::StatusCode::OK, "hello user123".to_string())
+        );
+        let (status, body) = send(request("/admin", Some(&system_token))).await;
+        assert_eq!(status, axum::http::StatusCode::UNAUTHORIZED);
+        assert!(body.contains("wrong_token_type"));
+        assert_eq!(
+            send(request("/admin", None)).await.0,
+            axum::http::StatusCode::UNAUTHORIZED
+        );
+
+        assert_eq!(
+            send(request("/feed", None)).await,
+            (axum::http::StatusCode::OK, "anonymous".to_string())
+        );
+        assert_eq!(
+            send(request("/feed", Some(&system_token))).await.1,
+            "billing"
+        );
+        assert_eq!(
+            send(request("/feed", Some("not-a-token"))).await.0,
+            axum::http::StatusCode::UNAUTHORIZED
+        );
+
+        // Roles take precedence over `optional`, so anonymous requests are turned away
+        let (status, body) = send(request("/reports", None)).await;
+        assert_eq!(status, axum::http::StatusCode::UNAUTHORIZED);
+        assert!(body.contains("missing_token"));
+        assert_eq!(
+            send(request("/reports", Some(&user_token))).await.0,
+            axum::http::StatusCode::OK
+        );
+
+        // Plain tower services work without a router
+        let service = tower::ServiceBuilder::new()
+            .layer(AuthLayer::system(auth_service.clone()))
+            .service_fn(
+                |request: axum::http::Request<axum::body::Body>| async move {
+                    let claims = request.extensions().get::<Claims>().unwrap();
+                    Ok::<_, std::convert::Infallible>(axum::http::Response::new(claims.sub.clone()))
+                },
+            );
+        let response = service
+            .clone()
+            .oneshot(request("/", Some(&system_token)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let response = service
+            .oneshot(request("/", Some(&user_token)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_authorization_hooks() {
+        // Tasks are owned by the user named before the dash in their id
+        struct TaskOwner;
+
+        #[async_trait]
+        impl AuthorizationHook<Claims> for TaskOwner {
+            async fn authorize(
+                &self,
+                claims: &Claims,
+                params: &HashMap<String, String>,
+            ) -> Result<bool, AuthError> {
+                let owner = params["taskId"].split('-').next();
+                Ok(claims.roles.contains(&"admin".to_string()) || owner == Some(&claims.sub))
+            }
+        }
+
+        let auth_service =
+            AuthService::new(b"test_secret").with_authorization_hook("task_owner", TaskOwner);
+        let token = |user: &str, roles: Vec<String>| {
+            auth_service
+                .create_token(user, roles, TokenType::User, 1)
+                .unwrap()
+        };
+        let alice = token("alice", vec![]);
+        let admin = token("root", vec!["admin".to_string()]);
+
+        let app = axum::Router::new()
+            .route(
+                "/tasks/{taskId}",
+                axum::routing::put(|| async { "updated" })
+                    .layer(axum::middleware::from_fn_with_state(
+                        auth_service.clone(),
+                        require_policy("task_owner"),
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        auth_service.clone(),
+                        user_auth_middleware,
+                    )),
+            )
+            .route(
+                "/projects/{projectId}",
+                axum::routing::put(|| async { "updated" })
+                    .layer(axum::middleware::from_fn_with_state(
+                        auth_service.clone(),
+                        require_policy("project_member"),
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        auth_service.clone(),
+                        user_auth_middleware,
+                    )),
+            )
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let status = |path: &'static str, token: &str| {
+            let request = client
+                .put(format!("{}{}", base, path))
+                .bearer_auth(token)
+                .send();
+            async move { request.await.unwrap().status() }
+        };
+        assert_eq!(status("/tasks/alice-1", &alice).await, 200);
+        assert_eq!(status("/tasks/bob-1", &alice).await, 403);
+        assert_eq!(status("/tasks/bob-1", &admin).await, 200);
+        // A route naming an unregistered hook fails closed
+        assert_eq!(status("/projects/apollo", &admin).await, 500);
+    }
+
+    #[tokio::test]
+    async fn test_optional_authentication() {
+        let auth_service = AuthService::new(b"test_secret");
+        let token = auth_service
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+
+        let optional =
+            || axum::middleware::from_fn_with_state(auth_service.clone(), optional_auth_middleware);
+        let app = axum::Router::new()
+            .route(
+                "/tasks/1",
+                axum::routing::get(|user: Option<AuthUser>| async move {
+                    user.map_or("anonymous".to_string(), |AuthUser(claims)| claims.sub)
+                })
+                .layer(optional()),
+            )
+            .route(
+                "/tasks/2",
+                axum::routing::get(|MaybeAuthUser(claims): MaybeAuthUser| async move {
+                    claims.map_or("anonymous".to_string(), |claims| claims.sub)
+                })
+                .layer(optional()),
+            )
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        for path in ["/tasks/1", "/tasks/2"] {
+            let url = format!("{}{}", base, path);
+            let response = client.get(&url).send().await.unwrap();
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.text().await.unwrap(), "anonymous");
+
+            let response = client.get(&url).bearer_auth(&token).send().await.unwrap();
+            assert_eq!(response.text().await.unwrap(), "user123");
+
+            let response = client
+                .get(&url)
+                .bearer_auth("garbage")
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 401);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_sources() {
+        let auth_service = AuthService::new(b"test_secret").with_token_sources(vec![
+            CredentialLocation::Bearer,
+            CredentialLocation::Header("x-api-token".to_string()),
+            CredentialLocation::Cookie("session".to_string()),
+        ]);
+        let token = auth_service
+            .create_token("user123", vec![], TokenType::User, 1)
+            .unwrap();
+
+        let app = axum::Router::new()
+            .route(
+                "/profile",
+                axum::routing::get(|AuthUser(claims): AuthUser| async move { claims.sub })
+                    .post(|| async { "saved" }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                auth_service.clone(),
+                user_auth_middleware,
+            ))
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/profile", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let response = client
+            .get(&url)
+            .header("Authorization", format!("bearer {}", token))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "user123");
+        let response = client
+            .get(&url)
+            .header("X-Api-Token", &token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "user123");
+        let response = client
+            .get(&url)
+            .header("Cookie", format!("theme=dark; session={}", token))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "user123");
+
+        // Cookie tokens on unsafe methods need the CSRF cookie echoed in a header
+        let response = client
+            .post(&url)
+            .header("Cookie", format!("session={}; csrf_token=abc", token))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "csrf_mismatch");
+        let response = client
+            .post(&url)
+            .header("Cookie", format!("session={}; csrf_token=abc", token))
+            .header("X-CSRF-Token", "abd")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        let response = client
+            .post(&url)
+            .header("Cookie", format!("session={}; csrf_token=abc", token))
+            .header("X-CSRF-Token", "abc")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        // Header tokens are not sent automatically by browsers, so no CSRF check applies
+        let response = client.post(&url).bearer_auth(&token).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
     #[test]
     fn test_role_checking() {
         let auth_service = AuthService::new(b"test_secret");
@@ -237,10 +2393,154 @@ mod tests {
             roles: vec!["user".to_string(), "admin".to_string()],
             exp: 0,
             token_type: TokenType::User,
+            iss: None,
+            aud: Vec::new(),
+            iat: None,
+            nbf: None,
+            jti: None,
+            family: None,
+            tenant: None,
         };
 
         assert!(auth_service.has_role(&claims, "user"));
         assert!(auth_service.has_role(&claims, "admin"));
         assert!(!auth_service.has_role(&claims, "superadmin"));
     }
+
+    #[tokio::test]
+    async fn test_token_endpoint() {
+        let path = std::env::temp_dir().join(format!("credentials-{}.yaml", uuid::Uuid::new_v4()));
+        let credentials = vec![
+            Credential::user("alice", "correct horse", vec!["admin".to_string()]).unwrap(),
+            Credential::client("billing", "client_secret", vec!["invoicer".to_string()])
+                .unwrap()
+                .with_claim("department", "finance".into()),
+        ];
+        std::fs::write(&path, serde_yaml::to_string(&credentials).unwrap()).unwrap();
+        let store = FileCredentialStore::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(verify_password(
+            "correct horse",
+            &credentials[0].password_hash
+        ));
+        assert!(!credentials[0].password_hash.contains("correct horse"));
+
+        // Extra claims of a credential reach the token through the service's claims type
+        let audit_path = std::env::temp_dir().join(format!("audit-{}.jsonl", uuid::Uuid::new_v4()));
+        let auth_service = AuthService::new(b"test_secret")
+            .with_credential_store(store)
+            .with_audit_sink(JsonLinesAuditSink::open(&audit_path).unwrap())
+            .with_claims::<CustomClaims<serde_json::Value>>();
+        let app = axum::Router::new()
+            .route("/auth/token", axum::routing::post(token_endpoint(600)))
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/auth/token", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let response = client
+            .post(&url)
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body("grant_type=password&username=alice&password=correct+horse")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["cache-control"], "no-store");
+        let body: TokenResponse = response.json().await.unwrap();
+        assert_eq!(body.token_type, "Bearer");
+        assert_eq!(body.expires_in, 600);
+        let claims = auth_service.verify_token(&body.access_token).unwrap();
+        assert_eq!(claims.subject(), "alice");
+        assert_eq!(claims.token_type(), TokenType::User);
+        assert_eq!(claims.roles(), ["admin".to_string()]);
+
+        let response = client
+            .post(&url)
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body("grant_type=password&username=alice&password=wrong")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "invalid_grant");
+
+        // Failed credential checks are audited
+        let events: Vec<AuditEvent> = std::fs::read_to_string(&audit_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        std::fs::remove_file(&audit_path).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].outcome, AuditOutcome::Failure);
+        assert_eq!(events[0].reason.as_deref(), Some("invalid_credentials"));
+        assert_eq!(events[0].method, "POST");
+        assert_eq!(events[0].route, "/auth/token");
+
+        // Clients authenticate with HTTP Basic and get system tokens with their extra claims
+        let response = client
+            .post(&url)
+            .basic_auth("billing", Some("client_secret"))
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body("grant_type=client_credentials")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body: TokenResponse = response.json().await.unwrap();
+        let claims = auth_service.verify_token(&body.access_token).unwrap();
+        assert_eq!(claims.token_type(), TokenType::System);
+        assert_eq!(claims.custom["department"], "finance");
+
+        // A client cannot use the password grant, nor a user the client credentials grant
+        let response = client
+            .post(&url)
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body("grant_type=password&username=billing&password=client_secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        let response = client
+            .post(&url)
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body("grant_type=client_credentials&client_id=alice&client_secret=correct+horse")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()["www-authenticate"], "Basic");
+
+        let response = client
+            .post(&url)
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body("grant_type=authorization_code&code=abc")
+            .send()
+            .await
+            .unwrap();
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "unsupported_grant_type");
+    }
+
+    #[test]
+    fn test_issue_token_lifetime() {
+        let auth_service = AuthService::new(b"test_secret");
+        let credential = Credential::user("alice", "correct horse", vec![]).unwrap();
+
+        let token = auth_service.issue_token(&credential, 600).unwrap();
+        let lifetime =
+            auth_service.verify_token(&token).unwrap().exp as i64 - chrono::Utc::now().timestamp();
+        assert!((590..=600).contains(&lifetime));
+
+        // An expiry past the representable range is refused, not wrapped around
+        for seconds in [i64::MAX, 0, -600] {
+            assert!(matches!(
+                auth_service.issue_token(&credential, seconds),
+                Err(AuthError::InvalidLifetime(s)) if s == seconds
+            ));
+        }
+    }
 }
@@ -0,0 +1,211 @@
+use crate::{AuthClaims, AuthError, AuthService, MiddlewareFuture};
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+// Role inheritance and role -> permission grants, e.g.
+//
+// inherits:
+//   superadmin: [admin]
+//   admin: [user]
+// permissions:
+//   user: ["tasks:read"]
+//   admin: ["tasks:write", "tasks:delete"]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthorizationPolicy {
+    // Role -> roles it includes
+    #[serde(default)]
+    pub inherits: HashMap<String, Vec<String>>,
+    // Role -> permissions granted to it and every role including it
+    #[serde(default)]
+    pub permissions: HashMap<String, Vec<String>>,
+}
+
+impl AuthorizationPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // YAML or JSON
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AuthError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AuthError::InvalidPolicy(format!("{}: {}", path.display(), e)))?;
+        serde_yaml::from_str(&contents)
+            .map_err(|e| AuthError::InvalidPolicy(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn with_inheritance(mut self, role: &str, includes: &[&str]) -> Self {
+        self.inherits
+            .entry(role.to_string())
+            .or_default()
+            .extend(includes.iter().map(|r| r.to_string()));
+        self
+    }
+
+    pub fn with_permissions(mut self, role: &str, permissions: &[&str]) -> Self {
+        self.permissions
+            .entry(role.to_string())
+            .or_default()
+            .extend(permissions.iter().map(|p| p.to_string()));
+        self
+    }
+
+    // The roles plus everything they inherit, transitively
+    pub fn effective_roles<'a>(&'a self, roles: &'a [String]) -> HashSet<&'a str> {
+        let mut effective = HashSet::new();
+        let mut pending: Vec<&str> = roles.iter().map(String::as_str).collect();
+        while let Some(role) = pending.pop() {
+            if effective.insert(role) {
+                pending.extend(
+                    self.inherits
+                        .get(role)
+                        .into_iter()
+                        .flatten()
+                        .map(String::as_str),
+                );
+            }
+        }
+        effective
+    }
+
+    pub fn effective_permissions<'a>(&'a self, roles: &'a [String]) -> HashSet<&'a str> {
+        self.effective_roles(roles)
+            .into_iter()
+            .filter_map(|role| self.permissions.get(role))
+            .flatten()
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+// Permission middleware factory: the token's roles must grant `permission`
+pub fn require_permission<C: AuthClaims>(
+    permission: &str,
+) -> impl Fn(State<AuthService<C>>, Request, Next) -> MiddlewareFuture + Clone {
+    require_all_permissions(vec![permission.to_string()])
+}
+
+pub fn require_all_permissions<C: AuthClaims>(
+    permissions: Vec<String>,
+) -> impl Fn(State<AuthService<C>>, Request, Next) -> MiddlewareFuture + Clone {
+    permission_middleware(permissions, true)
+}
+
+pub fn require_any_permission<C: AuthClaims>(
+    permissions: Vec<String>,
+) -> impl Fn(State<AuthService<C>>, Request, Next) -> MiddlewareFuture + Clone {
+    permission_middleware(permissions, false)
+}
+
+fn permission_middleware<C: AuthClaims>(
+    permissions: Vec<String>,
+    require_all: bool,
+) -> impl Fn(State<AuthService<C>>, Request, Next) -> MiddlewareFuture + Clone {
+    move |State(auth_service): State<AuthService<C>>, request: Request, next: Next| {
+        let permissions = permissions.clone();
+        Box::pin(async move {
+            let claims = auth_service.request_claims(&request)?;
+            let granted = if require_all {
+                auth_service.has_all_permissions(&claims, &permissions)
+            } else {
+                auth_service.has_any_permission(&claims, &permissions)
+            };
+            if !granted {
+                let error = AuthError::PermissionDenied(permissions);
+                return Err(auth_service.deny(&request, &claims, error));
+            }
+
+            Ok(next.run(request).await)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[tokio::test]
+    async fn test_role_hierarchy_and_permissions() {
+        let path = std::env::temp_dir().join(format!("policy-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"
+inherits:
+  superadmin: [admin]
+  admin: [user]
+permissions:
+  user: ["tasks:read"]
+  admin: ["tasks:write", "tasks:delete"]
+"#,
+        )
+        .unwrap();
+        let policy = AuthorizationPolicy::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let auth_service = AuthService::new(b"test_secret").with_policy(policy);
+
+        let superadmin = auth_service
+            .verify_token(
+                &auth_service
+                    .create_token("root", vec!["superadmin".to_string()], TokenType::User, 1)
+                    .unwrap(),
+            )
+            .unwrap();
+        assert!(auth_service.has_role(&superadmin, "user"));
+        assert!(auth_service.has_any_role(&superadmin, &["admin".to_string()]));
+        assert!(auth_service.has_permission(&superadmin, "tasks:read"));
+
+        let user_token = auth_service
+            .create_token("user123", vec!["user".to_string()], TokenType::User, 1)
+            .unwrap();
+        let user = auth_service.verify_token(&user_token).unwrap();
+        assert!(!auth_service.has_role(&user, "admin"));
+        let read_and_delete = vec!["tasks:read".to_string(), "tasks:delete".to_string()];
+        assert!(!auth_service.has_all_permissions(&user, &read_and_delete));
+        assert!(auth_service.has_any_permission(&user, &read_and_delete));
+
+        let admin_token = auth_service
+            .create_token("admin1", vec!["admin".to_string()], TokenType::User, 1)
+            .unwrap();
+        let app = axum::Router::new()
+            .route(
+                "/tasks",
+                axum::routing::delete(|| async { "deleted" })
+                    .layer(axum::middleware::from_fn_with_state(
+                        auth_service.clone(),
+                        require_permission("tasks:delete"),
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(
+                        auth_service.clone(),
+                        user_auth_middleware,
+                    )),
+            )
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/tasks", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let response = client
+            .delete(&url)
+            .bearer_auth(&admin_token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let response = client
+            .delete(&url)
+            .bearer_auth(&user_token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "permission_denied");
+    }
+}
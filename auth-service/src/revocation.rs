@@ -0,0 +1,118 @@
+use crate::AuthError;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+// Revoked token ids (`jti`, or a refresh token family), each kept until the token's `exp`
+#[async_trait]
+pub trait RevocationStore: Send + Sync {
+    // False when `id` was already revoked
+    async fn revoke(&self, id: &str, expires_at: usize) -> Result<bool, AuthError>;
+    async fn is_revoked(&self, id: &str) -> Result<bool, AuthError>;
+}
+
+#[derive(Default)]
+pub struct InMemoryRevocationStore {
+    revoked: RwLock<HashMap<String, usize>>,
+}
+
+impl InMemoryRevocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+// Entries for expired tokens no longer matter, as those fail verification anyway
+fn insert_revoked(revoked: &mut HashMap<String, usize>, id: &str, expires_at: usize) -> bool {
+    let now = chrono::Utc::now().timestamp() as usize;
+    revoked.retain(|_, expires_at| *expires_at > now);
+    revoked.insert(id.to_string(), expires_at).is_none()
+}
+
+#[async_trait]
+impl RevocationStore for InMemoryRevocationStore {
+    async fn revoke(&self, id: &str, expires_at: usize) -> Result<bool, AuthError> {
+        let mut revoked = self
+            .revoked
+            .write()
+            .expect("revocation store lock poisoned");
+        Ok(insert_revoked(&mut revoked, id, expires_at))
+    }
+
+    async fn is_revoked(&self, id: &str) -> Result<bool, AuthError> {
+        Ok(self
+            .revoked
+            .read()
+            .expect("revocation store lock poisoned")
+            .contains_key(id))
+    }
+}
+
+// JSON object of revoked id to expiry, loaded on open and rewritten on every revocation.
+// Revocations are serialized, and each rewrite goes through a temporary file renamed into
+// place, so the file always holds a complete list.
+pub struct FileRevocationStore {
+    path: PathBuf,
+    revoked: tokio::sync::Mutex<HashMap<String, usize>>,
+}
+
+impl FileRevocationStore {
+    // A missing file starts an empty list
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, AuthError> {
+        let path = path.into();
+        let revoked = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| {
+                AuthError::RevocationStoreUnavailable(format!("{}: {}", path.display(), e))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                return Err(AuthError::RevocationStoreUnavailable(format!(
+                    "{}: {}",
+                    path.display(),
+                    e
+                )));
+            }
+        };
+
+        Ok(Self {
+            path,
+            revoked: tokio::sync::Mutex::new(revoked),
+        })
+    }
+
+    async fn persist(&self, revoked: &HashMap<String, usize>) -> Result<(), AuthError> {
+        let unavailable = |e: std::io::Error| {
+            AuthError::RevocationStoreUnavailable(format!("{}: {}", self.path.display(), e))
+        };
+        let json = serde_json::to_string(revoked)
+            .map_err(|e| AuthError::RevocationStoreUnavailable(e.to_string()))?;
+
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        tokio::fs::write(&temp, json).await.map_err(unavailable)?;
+        tokio::fs::rename(&temp, &self.path)
+            .await
+            .map_err(unavailable)
+    }
+}
+
+#[async_trait]
+impl RevocationStore for FileRevocationStore {
+    async fn revoke(&self, id: &str, expires_at: usize) -> Result<bool, AuthError> {
+        let mut revoked = self.revoked.lock().await;
+        if !insert_revoked(&mut revoked, id, expires_at) {
+            return Ok(false);
+        }
+        // A revocation that was not written is not reported, nor kept
+        if let Err(error) = self.persist(&revoked).await {
+            revoked.remove(id);
+            return Err(error);
+        }
+        Ok(true)
+    }
+
+    async fn is_revoked(&self, id: &str) -> Result<bool, AuthError> {
+        Ok(self.revoked.lock().await.contains_key(id))
+    }
+}
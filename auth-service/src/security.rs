@@ -0,0 +1,272 @@
+use crate::{AuthClaims, AuthError, AuthService, MiddlewareFuture, TokenType, extract_token};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, Method, StatusCode, header},
+    middleware::Next,
+};
+
+// Where a security scheme's credential is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialLocation {
+    // `Authorization: Bearer <token>`, used by http bearer, oauth2 and openIdConnect schemes
+    Bearer,
+    Header(String),
+    Query(String),
+    Cookie(String),
+}
+
+impl CredentialLocation {
+    pub(crate) fn extract(&self, headers: &HeaderMap, query: Option<&str>) -> Option<String> {
+        match self {
+            CredentialLocation::Bearer => extract_token(headers).ok(),
+            CredentialLocation::Header(name) => headers
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            CredentialLocation::Query(name) => query?
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_string()),
+            CredentialLocation::Cookie(name) => headers
+                .get_all(header::COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|cookies| cookies.split(';'))
+                .filter_map(|cookie| cookie.trim().split_once('='))
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_string()),
+        }
+    }
+}
+
+// Where `AuthService` looks for bearer tokens, in order. Tokens read from a cookie are
+// sent by browsers automatically, so unsafe methods must also echo the CSRF cookie in a
+// header (double-submit).
+#[derive(Debug, Clone)]
+pub(crate) struct TokenSources {
+    pub(crate) locations: Vec<CredentialLocation>,
+    pub(crate) csrf_cookie: String,
+    pub(crate) csrf_header: String,
+}
+
+impl Default for TokenSources {
+    fn default() -> Self {
+        Self {
+            locations: vec![CredentialLocation::Bearer],
+            csrf_cookie: "csrf_token".to_string(),
+            csrf_header: "x-csrf-token".to_string(),
+        }
+    }
+}
+
+impl TokenSources {
+    pub(crate) fn extract(
+        &self,
+        method: &Method,
+        headers: &HeaderMap,
+        query: Option<&str>,
+    ) -> Result<String, AuthError> {
+        for location in &self.locations {
+            let Some(token) = location.extract(headers, query) else {
+                continue;
+            };
+            self.check_csrf(location, method, headers)?;
+            return Ok(token);
+        }
+        Err(AuthError::MissingToken)
+    }
+
+    // Credentials read from a cookie on unsafe methods need the double-submit
+    pub(crate) fn check_csrf(
+        &self,
+        location: &CredentialLocation,
+        method: &Method,
+        headers: &HeaderMap,
+    ) -> Result<(), AuthError> {
+        if !matches!(location, CredentialLocation::Cookie(_)) || method.is_safe() {
+            return Ok(());
+        }
+        let cookie = CredentialLocation::Cookie(self.csrf_cookie.clone()).extract(headers, None);
+        let header = CredentialLocation::Header(self.csrf_header.clone()).extract(headers, None);
+        match (cookie, header) {
+            (Some(cookie), Some(header)) if !cookie.is_empty() && cookie == header => Ok(()),
+            _ => Err(AuthError::CsrfMismatch),
+        }
+    }
+}
+
+// One scheme of a security requirement together with the scopes it demands
+#[derive(Debug, Clone)]
+pub struct SchemeRequirement {
+    pub location: CredentialLocation,
+    // Checked against the token's roles
+    pub scopes: Vec<String>,
+}
+
+impl SchemeRequirement {
+    pub fn bearer(scopes: Vec<String>) -> Self {
+        Self {
+            location: CredentialLocation::Bearer,
+            scopes,
+        }
+    }
+
+    pub fn api_key(location: CredentialLocation, scopes: Vec<String>) -> Self {
+        Self { location, scopes }
+    }
+}
+
+// OpenAPI `security` semantics: a request must satisfy every scheme of at least one
+// alternative. An alternative without schemes admits anonymous requests; a policy
+// without alternatives rejects everything.
+#[derive(Debug, Clone, Default)]
+pub struct SecurityPolicy {
+    alternatives: Vec<Vec<SchemeRequirement>>,
+    token_type: Option<TokenType>,
+}
+
+impl SecurityPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn or(mut self, schemes: Vec<SchemeRequirement>) -> Self {
+        self.alternatives.push(schemes);
+        self
+    }
+
+    pub fn allow_anonymous(self) -> Self {
+        self.or(Vec::new())
+    }
+
+    // Restrict tokens to one type; by default both user and system tokens are accepted
+    pub fn with_token_type(mut self, token_type: TokenType) -> Self {
+        self.token_type = Some(token_type);
+        self
+    }
+
+    async fn check_alternative<C: AuthClaims>(
+        &self,
+        schemes: &[SchemeRequirement],
+        auth_service: &AuthService<C>,
+        method: &Method,
+        headers: &HeaderMap,
+        query: Option<&str>,
+    ) -> Result<Option<C>, AuthError> {
+        let mut authenticated = None;
+        for scheme in schemes {
+            let credential = scheme
+                .location
+                .extract(headers, query)
+                .ok_or(AuthError::MissingToken)?;
+            auth_service
+                .token_sources
+                .check_csrf(&scheme.location, method, headers)?;
+            let claims = match scheme.location {
+                CredentialLocation::Bearer => auth_service.authenticate(&credential).await?,
+                // Without a store, API key schemes carry tokens under another name
+                _ if auth_service.has_api_key_store() => {
+                    auth_service.verify_api_key(&credential).await?
+                }
+                _ => auth_service.authenticate(&credential).await?,
+            };
+
+            if let Some(expected) = self.token_type
+                && claims.token_type() != expected
+            {
+                return Err(AuthError::WrongTokenType {
+                    expected,
+                    found: claims.token_type(),
+                });
+            }
+            if !scheme
+                .scopes
+                .iter()
+                .all(|scope| auth_service.has_role(&claims, scope))
+            {
+                return Err(AuthError::InsufficientScope(scheme.scopes.clone()));
+            }
+
+            authenticated.get_or_insert(claims);
+        }
+        Ok(authenticated)
+    }
+
+    pub async fn authorize<C: AuthClaims>(
+        &self,
+        auth_service: &AuthService<C>,
+        method: &Method,
+        headers: &HeaderMap,
+        query: Option<&str>,
+    ) -> Result<Option<C>, AuthError> {
+        // Anonymous alternatives are only considered once no other one matched, so presented
+        // credentials are still attached
+        let (anonymous, authenticated): (Vec<_>, Vec<_>) = self
+            .alternatives
+            .iter()
+            .partition(|schemes| schemes.is_empty());
+
+        let mut rejection = None;
+        for schemes in authenticated {
+            match self
+                .check_alternative(schemes, auth_service, method, headers, query)
+                .await
+            {
+                Ok(claims) => return Ok(claims),
+                // Report the most specific failure: a valid token lacking scopes over a missing one
+                Err(error) => {
+                    let replace = match &rejection {
+                        None | Some(AuthError::MissingToken) => true,
+                        Some(previous) => {
+                            error.status_code() == StatusCode::FORBIDDEN
+                                && previous.status_code() != StatusCode::FORBIDDEN
+                        }
+                    };
+                    if replace {
+                        rejection = Some(error);
+                    }
+                }
+            }
+        }
+        match rejection {
+            // Anonymous access is for requests without credentials, not with invalid ones
+            None | Some(AuthError::MissingToken) if !anonymous.is_empty() => Ok(None),
+            rejection => Err(rejection.unwrap_or(AuthError::MissingToken)),
+        }
+    }
+}
+
+// Security requirement middleware factory; inserts the first scheme's claims on success
+pub fn require_security<C: AuthClaims>(
+    policy: SecurityPolicy,
+) -> impl Fn(State<AuthService<C>>, Request, Next) -> MiddlewareFuture + Clone {
+    move |State(auth_service): State<AuthService<C>>, mut request: Request, next: Next| {
+        let policy = policy.clone();
+        Box::pin(async move {
+            let result = policy
+                .authorize(
+                    &auth_service,
+                    request.method(),
+                    request.headers(),
+                    request.uri().query(),
+                )
+                .await;
+            // Anonymous access is not an authentication attempt
+            if let Ok(None) = result {
+                return Ok(next.run(request).await);
+            }
+            let request_tenant = auth_service.request_tenant(&request);
+            let result = result
+                .map(|claims| claims.expect("checked for anonymous access"))
+                .and_then(|claims| {
+                    crate::check_tenant(&claims, request_tenant.as_deref())?;
+                    Ok(claims)
+                });
+            auth_service.record_audit(&request, &result);
+
+            crate::insert_claims(&mut request, result?);
+            Ok(next.run(request).await)
+        })
+    }
+}
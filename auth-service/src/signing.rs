@@ -0,0 +1,473 @@
+use crate::{AuthClaims, AuthError, AuthService, TokenType};
+use axum::{
+    body::Body,
+    extract::{OriginalUri, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub const KEY_ID_HEADER: &str = "x-signature-key-id";
+pub const TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+pub const NONCE_HEADER: &str = "x-signature-nonce";
+pub const SIGNATURE_HEADER: &str = "x-signature";
+
+// Bodies are buffered to be digested, so signed requests are capped at this size by default
+const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+// Shared secret of a calling service and the identity its signed requests authenticate as
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningKey {
+    pub key_id: String,
+    pub secret: String,
+    pub subject: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    // Additional claims for services using custom claims
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl SigningKey {
+    pub fn new(key_id: &str, secret: &str, subject: &str, roles: Vec<String>) -> Self {
+        Self {
+            key_id: key_id.to_string(),
+            secret: secret.to_string(),
+            subject: subject.to_string(),
+            roles,
+            extra: serde_json::Map::new(),
+        }
+    }
+}
+
+// HMAC-SHA256 request signatures over method, path, timestamp, nonce and body digest.
+// Timestamps outside `max_skew_seconds` are rejected and nonces are remembered for that
+// long, so a captured request cannot be replayed.
+pub struct RequestSigning {
+    keys: HashMap<String, SigningKey>,
+    max_skew_seconds: u64,
+    max_body_bytes: usize,
+    // Nonce -> time after which its timestamp is too old to be accepted anyway
+    nonces: Mutex<HashMap<String, u64>>,
+}
+
+impl Default for RequestSigning {
+    fn default() -> Self {
+        Self {
+            keys: HashMap::new(),
+            max_skew_seconds: 300,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            nonces: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl RequestSigning {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // YAML or JSON list of `SigningKey`s
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AuthError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AuthError::KeyProvider(format!("{}: {}", path.display(), e)))?;
+        let keys: Vec<SigningKey> = serde_yaml::from_str(&contents)
+            .map_err(|e| AuthError::KeyProvider(format!("{}: {}", path.display(), e)))?;
+        Ok(keys.into_iter().fold(Self::new(), Self::with_key))
+    }
+
+    pub fn with_key(mut self, key: SigningKey) -> Self {
+        self.keys.insert(key.key_id.clone(), key);
+        self
+    }
+
+    pub fn with_max_skew(mut self, seconds: u64) -> Self {
+        self.max_skew_seconds = seconds;
+        self
+    }
+
+    // Larger bodies are rejected with 413 before being buffered in full
+    pub fn with_max_body(mut self, bytes: usize) -> Self {
+        self.max_body_bytes = bytes;
+        self
+    }
+
+    // Claims document for a correctly signed, fresh and unseen request
+    pub(crate) fn verify(
+        &self,
+        method: &str,
+        path_and_query: &str,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<serde_json::Value, AuthError> {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .ok_or(AuthError::MissingToken)
+        };
+        let key_id = header(KEY_ID_HEADER)?;
+        let timestamp: u64 = header(TIMESTAMP_HEADER)?
+            .parse()
+            .map_err(|_| AuthError::MalformedToken)?;
+        let nonce = header(NONCE_HEADER)?;
+        let signature =
+            hex::decode(header(SIGNATURE_HEADER)?).map_err(|_| AuthError::MalformedToken)?;
+
+        let key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| AuthError::UnknownKey(key_id.to_string()))?;
+        signature_mac(&key.secret, method, path_and_query, timestamp, nonce, body)
+            .verify_slice(&signature)
+            .map_err(|_| AuthError::InvalidSignature)?;
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        if timestamp.abs_diff(now) > self.max_skew_seconds {
+            return Err(AuthError::SignatureExpired);
+        }
+        let expires_at = timestamp + self.max_skew_seconds;
+        {
+            let mut nonces = self.nonces.lock().expect("nonce cache lock poisoned");
+            nonces.retain(|_, expires_at| *expires_at >= now);
+            if nonces
+                .insert(format!("{}:{}", key_id, nonce), expires_at)
+                .is_some()
+            {
+                return Err(AuthError::ReplayedRequest);
+            }
+        }
+
+        let mut claims = key.extra.clone();
+        claims.insert("sub".to_string(), key.subject.clone().into());
+        claims.insert("roles".to_string(), key.roles.clone().into());
+        claims.insert("exp".to_string(), expires_at.into());
+        claims.insert(
+            "type".to_string(),
+            serde_json::to_value(TokenType::System).expect("token type serializes"),
+        );
+        Ok(serde_json::Value::Object(claims))
+    }
+}
+
+fn signature_mac(
+    secret: &str,
+    method: &str,
+    path_and_query: &str,
+    timestamp: u64,
+    nonce: &str,
+    body: &[u8],
+) -> Hmac<Sha256> {
+    let canonical = format!(
+        "{}\n{}\n{}\n{}\n{}",
+        method.to_ascii_uppercase(),
+        path_and_query,
+        timestamp,
+        nonce,
+        hex::encode(Sha256::digest(body))
+    );
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(canonical.as_bytes());
+    mac
+}
+
+// Headers a calling service adds to sign a request, e.g.
+// `sign_request("billing", secret, "POST", "/invoices?draft=true", &body)`
+pub fn sign_request(
+    key_id: &str,
+    secret: &str,
+    method: &str,
+    path_and_query: &str,
+    body: &[u8],
+) -> Vec<(&'static str, String)> {
+    let timestamp = chrono::Utc::now().timestamp() as u64;
+    let nonce = uuid::Uuid::new_v4().to_string();
+    let signature = signature_mac(secret, method, path_and_query, timestamp, &nonce, body)
+        .finalize()
+        .into_bytes();
+
+    vec![
+        (KEY_ID_HEADER, key_id.to_string()),
+        (TIMESTAMP_HEADER, timestamp.to_string()),
+        (NONCE_HEADER, nonce),
+        (SIGNATURE_HEADER, hex::encode(signature)),
+    ]
+}
+
+// Middleware for system calls authenticated by request signature instead of a bearer token
+pub async fn signed_request_middleware<C: AuthClaims>(
+    State(auth_service): State<AuthService<C>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let signing = auth_service
+        .request_signing()
+        .ok_or(AuthError::SigningKeyUnavailable)?;
+
+    let (parts, body) = request.into_parts();
+    let body = axum::body::to_bytes(body, signing.max_body_bytes)
+        .await
+        .map_err(|error| {
+            let too_large = std::error::Error::source(&error)
+                .is_some_and(|source| source.is::<http_body_util::LengthLimitError>());
+            if too_large {
+                AuthError::PayloadTooLarge(signing.max_body_bytes)
+            } else {
+                AuthError::MalformedToken
+            }
+        })?;
+    let mut request = Request::from_parts(parts, Body::from(body.clone()));
+
+    // Signed over the path the caller sent, which differs from `uri()` inside `nest`
+    let path_and_query = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or(request.uri(), |uri| &uri.0)
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    let request_tenant = auth_service.request_tenant(&request);
+    let result = signing
+        .verify(
+            request.method().as_str(),
+            path_and_query,
+            request.headers(),
+            &body,
+        )
+        .and_then(|claims| serde_json::from_value::<C>(claims).map_err(|_| AuthError::InvalidToken))
+        .and_then(|claims| {
+            crate::check_tenant(&claims, request_tenant.as_deref())?;
+            Ok(claims)
+        });
+    auth_service.record_audit(&request, &result);
+
+    crate::insert_claims(&mut request, result?);
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[tokio::test]
+    async fn test_signed_requests() {
+        let auth_service = AuthService::new(b"test_secret").with_request_signing(
+            RequestSigning::new().with_key(SigningKey::new(
+                "billing",
+                "shared_secret",
+                "billing-service",
+                vec!["invoicer".to_string()],
+            )),
+        );
+
+        let app = axum::Router::new()
+            .route(
+                "/invoices",
+                axum::routing::post(|AuthUser(claims): AuthUser, body: String| async move {
+                    assert_eq!(claims.token_type, TokenType::System);
+                    assert_eq!(claims.roles, vec!["invoicer".to_string()]);
+                    format!("{}:{}", claims.sub, body)
+                })
+                .layer(axum::middleware::from_fn_with_state(
+                    auth_service.clone(),
+                    signed_request_middleware,
+                )),
+            )
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/invoices?draft=true",
+            listener.local_addr().unwrap()
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let send = |headers: Vec<(&'static str, String)>, body: &'static str| {
+            let mut request = client.post(&url).body(body);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            request.send()
+        };
+
+        let headers = sign_request(
+            "billing",
+            "shared_secret",
+            "POST",
+            "/invoices?draft=true",
+            b"42",
+        );
+        let response = send(headers.clone(), "42").await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "billing-service:42");
+
+        // The same signature cannot be used twice
+        let response = send(headers, "42").await.unwrap();
+        assert_eq!(response.status(), 401);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "replayed_request");
+
+        let headers = sign_request(
+            "billing",
+            "shared_secret",
+            "POST",
+            "/invoices?draft=true",
+            b"42",
+        );
+        let response = send(headers, "4200").await.unwrap();
+        assert_eq!(response.status(), 401);
+
+        let headers = sign_request(
+            "billing",
+            "wrong_secret",
+            "POST",
+            "/invoices?draft=true",
+            b"42",
+        );
+        let response = send(headers, "42").await.unwrap();
+        assert_eq!(response.status(), 401);
+
+        let response = send(Vec::new(), "42").await.unwrap();
+        assert_eq!(response.status(), 401);
+
+        // A stale timestamp is rejected even with a valid signature
+        let stale = RequestSigning::new()
+            .with_max_skew(0)
+            .with_key(SigningKey::new(
+                "billing",
+                "shared_secret",
+                "billing-service",
+                vec![],
+            ));
+        let mut headers = axum::http::HeaderMap::new();
+        for (name, value) in sign_request("billing", "shared_secret", "GET", "/", b"") {
+            headers.insert(name, value.parse().unwrap());
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        assert!(matches!(
+            stale.verify("GET", "/", &headers, b""),
+            Err(AuthError::SignatureExpired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_signed_requests_under_nest() {
+        let auth_service = AuthService::new(b"test_secret").with_request_signing(
+            RequestSigning::new()
+                .with_max_body(16)
+                .with_key(SigningKey::new(
+                    "billing",
+                    "shared_secret",
+                    "billing-service",
+                    vec![],
+                )),
+        );
+
+        let invoices = axum::Router::new()
+            .route(
+                "/invoices",
+                axum::routing::post(|AuthUser(claims): AuthUser| async move { claims.sub }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                auth_service.clone(),
+                signed_request_middleware,
+            ));
+        let app = axum::Router::new()
+            .nest("/billing", invoices)
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/billing/invoices?draft=true",
+            listener.local_addr().unwrap()
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let send = |body: &'static str| {
+            let mut request = client.post(&url).body(body);
+            for (name, value) in sign_request(
+                "billing",
+                "shared_secret",
+                "POST",
+                "/billing/invoices?draft=true",
+                body.as_bytes(),
+            ) {
+                request = request.header(name, value);
+            }
+            request.send()
+        };
+
+        // Signed over the full path, not the one seen inside the nested router
+        let response = send("42").await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "billing-service");
+
+        let response = send("a body longer than sixteen bytes").await.unwrap();
+        assert_eq!(response.status(), 413);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "payload_too_large");
+    }
+
+    #[test]
+    fn test_nonce_expiry() {
+        let signing = RequestSigning::new()
+            .with_max_skew(60)
+            .with_key(SigningKey::new(
+                "billing",
+                "shared_secret",
+                "billing-service",
+                vec![],
+            ));
+        let headers = |signed: Vec<(&'static str, String)>| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in signed {
+                headers.insert(name, value.parse().unwrap());
+            }
+            headers
+        };
+
+        // Nonces whose timestamps can no longer pass the skew check are dropped
+        signing
+            .nonces
+            .lock()
+            .unwrap()
+            .insert("billing:stale".to_string(), 0);
+        let fresh = headers(sign_request("billing", "shared_secret", "GET", "/", b""));
+        assert!(signing.verify("GET", "/", &fresh, b"").is_ok());
+        {
+            let nonces = signing.nonces.lock().unwrap();
+            assert!(!nonces.contains_key("billing:stale"));
+            assert_eq!(nonces.len(), 1);
+        }
+        assert!(matches!(
+            signing.verify("GET", "/", &fresh, b""),
+            Err(AuthError::ReplayedRequest)
+        ));
+
+        // Timestamps older than the skew window are refused before the nonce is recorded
+        let timestamp = chrono::Utc::now().timestamp() as u64 - 120;
+        let signature = signature_mac("shared_secret", "GET", "/", timestamp, "old", b"")
+            .finalize()
+            .into_bytes();
+        let old = headers(vec![
+            (KEY_ID_HEADER, "billing".to_string()),
+            (TIMESTAMP_HEADER, timestamp.to_string()),
+            (NONCE_HEADER, "old".to_string()),
+            (SIGNATURE_HEADER, hex::encode(signature)),
+        ]);
+        assert!(matches!(
+            signing.verify("GET", "/", &old, b""),
+            Err(AuthError::SignatureExpired)
+        ));
+        assert!(!signing.nonces.lock().unwrap().contains_key("billing:old"));
+    }
+}
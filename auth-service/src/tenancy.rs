@@ -0,0 +1,439 @@
+use crate::{AuthError, AuthorizationPolicy, KeySet};
+use axum::extract::MatchedPath;
+use axum::http::Request;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Where a request names the tenant it addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TenantSource {
+    // Parameter of the matched route, e.g. `tenant` in `/tenants/{tenant}/tasks`
+    PathParam(String),
+    // First label of the `Host` header, e.g. `acme` in `acme.example.com`
+    Subdomain,
+    Header(String),
+}
+
+// `path:<param>`, `subdomain` or `header:<name>`, as written in tenants files
+impl FromStr for TenantSource {
+    type Err = AuthError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source.split_once(':') {
+            Some(("path", param)) => Ok(TenantSource::PathParam(param.to_string())),
+            Some(("header", name)) => Ok(TenantSource::Header(name.to_string())),
+            None if source == "subdomain" => Ok(TenantSource::Subdomain),
+            _ => Err(AuthError::InvalidKey(format!(
+                "unknown tenant scope {}, expected path:<param>, subdomain or header:<name>",
+                source
+            ))),
+        }
+    }
+}
+
+// Per-tenant signing keys and role sets. A token's tenant comes from its `tenant` claim, or
+// from `key_header` when set; the tenant's keys must verify it. With a `scope`, the token's
+// tenant must also be the one the request addresses.
+#[derive(Clone, Default)]
+pub struct Tenants {
+    keys: HashMap<String, KeySet>,
+    policies: HashMap<String, Arc<AuthorizationPolicy>>,
+    key_header: Option<String>,
+    scope: Option<TenantSource>,
+}
+
+// Tenants file, YAML or JSON; relative paths are resolved against the file's directory
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TenantsFile {
+    key_header: Option<String>,
+    scope: Option<String>,
+    tenants: HashMap<String, TenantEntry>,
+}
+
+#[derive(Deserialize)]
+struct TenantEntry {
+    // HS256 secret
+    secret: Option<String>,
+    // JWKS file with the tenant's verification keys
+    jwks: Option<PathBuf>,
+    // Role inheritance and permissions file
+    policy: Option<PathBuf>,
+}
+
+impl Tenants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AuthError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AuthError::InvalidKey(format!("{}: {}", path.display(), e)))?;
+        let file: TenantsFile = serde_yaml::from_str(&contents)
+            .map_err(|e| AuthError::InvalidKey(format!("{}: {}", path.display(), e)))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        let mut tenants = Self {
+            key_header: file.key_header,
+            scope: file.scope.as_deref().map(str::parse).transpose()?,
+            ..Self::default()
+        };
+        for (tenant, entry) in file.tenants {
+            let keys = match (entry.secret, entry.jwks) {
+                (Some(secret), None) => KeySet::single(
+                    Algorithm::HS256,
+                    Some(EncodingKey::from_secret(secret.as_bytes())),
+                    DecodingKey::from_secret(secret.as_bytes()),
+                ),
+                (None, Some(jwks)) => KeySet::from_jwks_file(dir.join(jwks))?,
+                _ => {
+                    return Err(AuthError::InvalidKey(format!(
+                        "tenant {} needs either a secret or a jwks file",
+                        tenant
+                    )));
+                }
+            };
+            if let Some(policy) = entry.policy {
+                let policy = AuthorizationPolicy::from_file(dir.join(policy))?;
+                tenants = tenants.with_tenant_policy(&tenant, policy);
+            }
+            tenants = tenants.with_tenant(&tenant, keys);
+        }
+        Ok(tenants)
+    }
+
+    pub fn with_tenant(mut self, tenant: &str, keys: KeySet) -> Self {
+        self.keys.insert(tenant.to_string(), keys);
+        self
+    }
+
+    // Roles and permissions of the tenant's users, instead of the service-wide policy
+    pub fn with_tenant_policy(mut self, tenant: &str, policy: AuthorizationPolicy) -> Self {
+        self.policies.insert(tenant.to_string(), Arc::new(policy));
+        self
+    }
+
+    // Header naming the tenant whose keys verify the token, e.g. `x-tenant-id`
+    pub fn with_key_header(mut self, header: &str) -> Self {
+        self.key_header = Some(header.to_string());
+        self
+    }
+
+    pub fn scoped_by(mut self, source: TenantSource) -> Self {
+        self.scope = Some(source);
+        self
+    }
+
+    pub(crate) fn keys(&self, tenant: &str) -> Result<&KeySet, AuthError> {
+        self.keys
+            .get(tenant)
+            .ok_or_else(|| AuthError::UnknownTenant(tenant.to_string()))
+    }
+
+    pub(crate) fn policy(&self, tenant: &str) -> Option<&AuthorizationPolicy> {
+        self.policies.get(tenant).map(Arc::as_ref)
+    }
+
+    pub(crate) fn key_tenant<B>(&self, request: &Request<B>) -> Option<String> {
+        let header = self.key_header.as_deref()?;
+        header_value(request, header)
+    }
+
+    // Tenant addressed by the request, when the service is scoped and the request names one
+    pub(crate) fn request_tenant<B>(&self, request: &Request<B>) -> Option<String> {
+        match self.scope.as_ref()? {
+            TenantSource::PathParam(name) => path_param(request, name),
+            TenantSource::Subdomain => {
+                let host = header_value(request, "host")?;
+                // Addressed by IP, there is no subdomain
+                let name = host
+                    .rsplit_once(':')
+                    .map_or(host.as_str(), |(name, _)| name);
+                if name.parse::<std::net::Ipv4Addr>().is_ok() || name.starts_with('[') {
+                    return None;
+                }
+                let (subdomain, _) = host.split_once('.')?;
+                Some(subdomain.to_string())
+            }
+            TenantSource::Header(name) => header_value(request, name),
+        }
+    }
+}
+
+fn header_value<B>(request: &Request<B>, name: &str) -> Option<String> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+// Segment of the request path at the parameter's position in the matched route
+fn path_param<B>(request: &Request<B>, name: &str) -> Option<String> {
+    let route = request.extensions().get::<MatchedPath>()?;
+    let placeholder = format!("{{{}}}", name);
+    let position = route
+        .as_str()
+        .split('/')
+        .position(|segment| segment == placeholder)?;
+    request
+        .uri()
+        .path()
+        .split('/')
+        .nth(position)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use axum::http::Request;
+
+    #[tokio::test]
+    async fn test_tenant_scoping() {
+        let dir = std::env::temp_dir().join(format!("tenants-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("acme-policy.yaml"), "inherits:\n  admin: [user]\n").unwrap();
+        std::fs::write(
+            dir.join("tenants.yaml"),
+            r#"
+scope: "path:tenant"
+tenants:
+  acme:
+    secret: "acme_secret"
+    policy: "acme-policy.yaml"
+  globex:
+    secret: "globex_secret"
+"#,
+        )
+        .unwrap();
+        let tenants = Tenants::from_file(dir.join("tenants.yaml")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let auth_service = AuthService::new(b"platform_secret").with_tenants(tenants);
+        let tenant_token = |tenant: &str, roles: Vec<String>| {
+            let mut claims = auth_service.standard_claims("alice", roles, TokenType::User, 1);
+            claims.tenant = Some(tenant.to_string());
+            auth_service.sign(&claims).unwrap()
+        };
+        let acme_token = tenant_token("acme", vec!["admin".to_string()]);
+        let globex_token = tenant_token("globex", vec!["admin".to_string()]);
+
+        // Each tenant's key verifies only its own tokens, and its policy sets its roles
+        let acme = auth_service.verify_token(&acme_token).unwrap();
+        assert_eq!(acme.tenant.as_deref(), Some("acme"));
+        assert!(auth_service.has_role(&acme, "user"));
+        let globex = auth_service.verify_token(&globex_token).unwrap();
+        assert!(!auth_service.has_role(&globex, "user"));
+        assert!(
+            AuthService::new(b"acme_secret")
+                .verify_token(&globex_token)
+                .is_err()
+        );
+
+        let mut forged = auth_service.standard_claims("alice", vec![], TokenType::User, 1);
+        forged.tenant = Some("globex".to_string());
+        let forged = AuthService::new(b"acme_secret").sign(&forged).unwrap();
+        assert!(matches!(
+            auth_service.verify_token(&forged),
+            Err(AuthError::InvalidSignature)
+        ));
+        let mut unknown = auth_service.standard_claims("alice", vec![], TokenType::User, 1);
+        unknown.tenant = Some("initech".to_string());
+        assert!(matches!(
+            auth_service.sign(&unknown),
+            Err(AuthError::UnknownTenant(_))
+        ));
+
+        let app = axum::Router::new()
+            .route(
+                "/tenants/{tenant}/tasks",
+                axum::routing::get(|Tenant(tenant): Tenant| async move { tenant }).layer(
+                    axum::middleware::from_fn_with_state(
+                        auth_service.clone(),
+                        user_auth_middleware,
+                    ),
+                ),
+            )
+            // Shaped like a generated handler, which also serves platform tokens
+            .route(
+                "/tasks",
+                axum::routing::get(
+                    |AuthUser(claims): AuthUser, auth_tenant: Option<Tenant>| async move {
+                        let tenant = auth_tenant.map_or("platform".to_string(), |Tenant(t)| t);
+                        format!("{}:{}", claims.sub, tenant)
+                    },
+                )
+                .layer(axum::middleware::from_fn_with_state(
+                    auth_service.clone(),
+                    user_auth_middleware,
+                )),
+            )
+            .with_state(auth_service.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let response = client
+            .get(format!("{}/tenants/acme/tasks", base))
+            .bearer_auth(&acme_token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "acme");
+
+        let response = client
+            .get(format!("{}/tenants/globex/tasks", base))
+            .bearer_auth(&acme_token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "tenant_mismatch");
+
+        // Platform tokens without a tenant cannot address a tenant's routes
+        let platform_token = auth_service
+            .create_token("ops", vec![], TokenType::User, 1)
+            .unwrap();
+        let response = client
+            .get(format!("{}/tenants/acme/tasks", base))
+            .bearer_auth(&platform_token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+
+        for (token, expected) in [
+            (&platform_token, "ops:platform"),
+            (&acme_token, "alice:acme"),
+        ] {
+            let response = client
+                .get(format!("{}/tasks", base))
+                .bearer_auth(token)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.text().await.unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_tenant_key_header() {
+        let acme_keys = KeySet::new();
+        acme_keys.add_signing_secret("acme-1", Algorithm::HS256, b"acme_secret");
+        let auth_service = AuthService::new(b"platform_secret").with_tenants(
+            Tenants::new()
+                .with_tenant("acme", acme_keys)
+                .with_key_header("x-tenant-id")
+                .scoped_by(TenantSource::Subdomain),
+        );
+        let mut claims = auth_service.standard_claims("alice", vec![], TokenType::User, 1);
+        claims.tenant = Some("acme".to_string());
+        let token = auth_service.sign(&claims).unwrap();
+
+        let request = |host: &str, tenant: &str| {
+            axum::http::Request::builder()
+                .uri("/tasks")
+                .header("host", host)
+                .header("x-tenant-id", tenant)
+                .header("authorization", format!("Bearer {}", token))
+                .body(())
+                .unwrap()
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let authenticate = |request: axum::http::Request<()>| {
+            runtime.block_on(auth_service.authenticate_request(&request, None))
+        };
+
+        let claims = authenticate(request("acme.example.com", "acme")).unwrap();
+        assert_eq!(claims.tenant_id(), Some("acme"));
+        assert!(matches!(
+            authenticate(request("globex.example.com", "acme")),
+            Err(AuthError::TenantMismatch)
+        ));
+        assert!(matches!(
+            authenticate(request("acme.example.com", "globex")),
+            Err(AuthError::UnknownTenant(_))
+        ));
+    }
+
+    #[test]
+    fn test_tenant_source_from_str() {
+        assert_eq!(
+            "path:tenant".parse::<TenantSource>().unwrap(),
+            TenantSource::PathParam("tenant".to_string())
+        );
+        assert_eq!(
+            "header:x-tenant".parse::<TenantSource>().unwrap(),
+            TenantSource::Header("x-tenant".to_string())
+        );
+        assert_eq!(
+            "subdomain".parse::<TenantSource>().unwrap(),
+            TenantSource::Subdomain
+        );
+        for invalid in ["", "path", "subdomain:acme", "query:tenant"] {
+            assert!(matches!(
+                invalid.parse::<TenantSource>(),
+                Err(AuthError::InvalidKey(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_request_tenant_from_subdomain() {
+        let tenants = Tenants::new().scoped_by(TenantSource::Subdomain);
+        let tenant = |host: &str| {
+            let request = Request::builder().header("host", host).body(()).unwrap();
+            tenants.request_tenant(&request)
+        };
+        assert_eq!(tenant("acme.example.com"), Some("acme".to_string()));
+        assert_eq!(tenant("acme.example.com:8080"), Some("acme".to_string()));
+        assert_eq!(tenant("localhost"), None);
+        assert_eq!(tenant("localhost:3000"), None);
+        assert_eq!(tenant("10.0.0.1"), None);
+        assert_eq!(tenant("10.0.0.1:8080"), None);
+        assert_eq!(tenant("[::1]"), None);
+        assert_eq!(tenant("[::1]:3000"), None);
+        assert_eq!(tenants.request_tenant(&Request::new(())), None);
+    }
+
+    #[tokio::test]
+    async fn test_path_param() {
+        use http_body_util::BodyExt;
+        use tower::ServiceExt;
+
+        // `MatchedPath` is only set by the router
+        let app = axum::Router::new().route(
+            "/tenants/{tenant}/tasks/{id}",
+            axum::routing::get(|request: axum::extract::Request| async move {
+                format!(
+                    "{:?} {:?} {:?}",
+                    path_param(&request, "tenant"),
+                    path_param(&request, "id"),
+                    path_param(&request, "missing")
+                )
+            }),
+        );
+        let request = Request::get("/tenants/acme/tasks/7")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"Some("acme") Some("7") None"#);
+
+        let request = Request::get("/tenants/acme").body(()).unwrap();
+        assert_eq!(path_param(&request, "tenant"), None);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{AuthClaims, AuthError, AuthService, TokenType};
+use axum::{
+    Form, Json,
+    extract::{State, rejection::FormRejection},
+    http::{HeaderMap, HeaderValue, Request, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+
+// `application/x-www-form-urlencoded` token request (RFC 6749 sections 4.3 and 4.4)
+#[derive(Debug, Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Clients may authenticate with HTTP Basic instead
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+}
+
+// RFC 6749 section 5.2 error response
+struct TokenError {
+    status: StatusCode,
+    error: &'static str,
+    description: String,
+}
+
+impl TokenError {
+    fn invalid_request(description: &str) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            error: "invalid_request",
+            description: description.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for TokenError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "error": self.error,
+            "error_description": self.description,
+        });
+        let mut response = (self.status, no_store(), Json(body)).into_response();
+        if self.status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Basic"));
+        }
+        response
+    }
+}
+
+// Token responses must not be cached (RFC 6749 section 5.1)
+fn no_store() -> [(header::HeaderName, HeaderValue); 2] {
+    [
+        (header::CACHE_CONTROL, HeaderValue::from_static("no-store")),
+        (header::PRAGMA, HeaderValue::from_static("no-cache")),
+    ]
+}
+
+type TokenFuture = Pin<Box<dyn Future<Output = Response> + Send>>;
+
+// Token endpoint handler factory issuing user tokens for the password grant and system tokens
+// for the client credentials grant, e.g. `post(token_endpoint(3600))`. Failed credential
+// checks are recorded by the audit sink.
+pub fn token_endpoint<C: AuthClaims>(
+    expires_in_seconds: i64,
+) -> impl Fn(State<AuthService<C>>, Parts, Result<Form<TokenRequest>, FormRejection>) -> TokenFuture
++ Clone {
+    move |State(auth_service): State<AuthService<C>>,
+          parts: Parts,
+          form: Result<Form<TokenRequest>, FormRejection>| {
+        Box::pin(async move {
+            let request = Request::from_parts(parts, ());
+            match issue(&auth_service, &request, form, expires_in_seconds).await {
+                Ok(response) => (no_store(), Json(response)).into_response(),
+                Err(error) => error.into_response(),
+            }
+        })
+    }
+}
+
+async fn issue<C: AuthClaims>(
+    auth_service: &AuthService<C>,
+    request: &Request<()>,
+    form: Result<Form<TokenRequest>, FormRejection>,
+    expires_in_seconds: i64,
+) -> Result<TokenResponse, TokenError> {
+    let Form(form) =
+        form.map_err(|rejection| TokenError::invalid_request(&rejection.body_text()))?;
+
+    let (username, password, token_type) = match form.grant_type.as_str() {
+        "password" => match (form.username, form.password) {
+            (Some(username), Some(password)) => (username, password, TokenType::User),
+            _ => {
+                return Err(TokenError::invalid_request(
+                    "username and password are required",
+                ));
+            }
+        },
+        "client_credentials" => match basic_credentials(request.headers())
+            .or_else(|| form.client_id.zip(form.client_secret))
+        {
+            Some((client_id, secret)) => (client_id, secret, TokenType::System),
+            None => {
+                return Err(TokenError {
+                    status: StatusCode::UNAUTHORIZED,
+                    error: "invalid_client",
+                    description: "client authentication is required".to_string(),
+                });
+            }
+        },
+        _ => {
+            return Err(TokenError {
+                status: StatusCode::BAD_REQUEST,
+                error: "unsupported_grant_type",
+                description: format!("unsupported grant type {}", form.grant_type),
+            });
+        }
+    };
+
+    let credential = auth_service
+        .verify_credentials(&username, &password, token_type)
+        .await
+        .map_err(|error| {
+            let token_error = match error {
+                AuthError::InvalidCredentials if token_type == TokenType::System => TokenError {
+                    status: StatusCode::UNAUTHORIZED,
+                    error: "invalid_client",
+                    description: error.to_string(),
+                },
+                AuthError::InvalidCredentials => TokenError {
+                    status: StatusCode::BAD_REQUEST,
+                    error: "invalid_grant",
+                    description: error.to_string(),
+                },
+                _ => server_error(),
+            };
+            auth_service.record_audit(request, &Err(error));
+            token_error
+        })?;
+    let access_token = auth_service
+        .issue_token(&credential, expires_in_seconds)
+        .map_err(|_| server_error())?;
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: expires_in_seconds,
+    })
+}
+
+fn server_error() -> TokenError {
+    TokenError {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        error: "server_error",
+        description: "Authentication is unavailable".to_string(),
+    }
+}
+
+// Client id and secret from an `Authorization: Basic` header
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, encoded) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let (client_id, secret) = std::str::from_utf8(&decoded).ok()?.split_once(':')?;
+    Some((client_id.to_string(), secret.to_string()))
+}
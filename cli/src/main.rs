@@ -1,16 +1,22 @@
 use anyhow::Result;
-use clap::Parser;
-use code_generator::CodeGenerator;
+use clap::{Parser, Subcommand};
+use code_generator::{CodeGenerator, GeneratorOptions};
 use openapi_parser::OpenApiSpec;
 use std::path::PathBuf;
 
+mod token;
+
 #[derive(Parser)]
 #[command(name = "openapi-to-axum")]
 #[command(about = "Generate Rust Axum code from OpenAPI specifications")]
+#[command(subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Input OpenAPI file (JSON or YAML)
-    #[arg(short, long)]
-    input: PathBuf,
+    #[arg(short, long, required = true)]
+    input: Option<PathBuf>,
 
     /// Output directory for generated code
     #[arg(short, long)]
@@ -19,23 +25,49 @@ struct Cli {
     /// Generate example server code
     #[arg(short, long)]
     example: bool,
+
+    /// Generate separate request models for schemas with readOnly/writeOnly properties
+    #[arg(long)]
+    split_models: bool,
+
+    /// Embed referenced allOf members with #[serde(flatten)] instead of copying their fields
+    #[arg(long)]
+    flatten_all_of: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Mint, decode and verify tokens for testing generated servers
+    #[command(subcommand)]
+    Token(token::TokenCommand),
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    if let Some(Command::Token(command)) = cli.command {
+        return token::run(command);
+    }
+    let input = cli
+        .input
+        .expect("clap requires --input without a subcommand");
+
     // Read input file
-    let content = std::fs::read_to_string(&cli.input)?;
+    let content = std::fs::read_to_string(&input)?;
 
     // Parse OpenAPI spec
-    let spec = if cli.input.extension().map(|e| e == "json").unwrap_or(false) {
+    let spec = if input.extension().map(|e| e == "json").unwrap_or(false) {
         OpenApiSpec::from_json(&content)?
     } else {
         OpenApiSpec::from_yaml(&content)?
     };
 
     // Generate code
-    let generated_tokens = CodeGenerator::generate_axum_app(&spec);
+    let options = GeneratorOptions {
+        split_read_write_models: cli.split_models,
+        flatten_all_of: cli.flatten_all_of,
+    };
+    let generated_tokens = CodeGenerator::generate_axum_app_with_options(&spec, &options)?;
 
     // Format the generated code properly
     let syntax_tree = syn::parse2(generated_tokens)?;
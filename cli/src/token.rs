@@ -0,0 +1,324 @@
+use anyhow::{Context, Result, bail};
+use auth_service::{AuthConfig, AuthService, Claims, KeySet, TokenType};
+use clap::{Args, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+#[derive(Subcommand)]
+pub enum TokenCommand {
+    /// Sign a token for testing a generated server
+    Mint(MintArgs),
+    /// Print a token's header and claims without verifying it
+    Decode {
+        /// The JWT to decode
+        token: String,
+    },
+    /// Verify a token's signature and claims, printing the claims when valid
+    Verify(VerifyArgs),
+    /// Print the argon2 hash of a password or client secret for a credentials file
+    HashPassword {
+        /// The password to hash
+        password: String,
+    },
+}
+
+#[derive(Args)]
+pub struct MintArgs {
+    /// Subject (`sub`) of the token
+    #[arg(long)]
+    sub: String,
+
+    /// Comma-separated roles
+    #[arg(long, value_delimiter = ',')]
+    roles: Vec<String>,
+
+    /// Token type
+    #[arg(long = "type", value_enum, default_value_t = TokenKind::User)]
+    token_type: TokenKind,
+
+    /// Lifetime, e.g. `90s`, `30m`, `2h` or `7d`
+    #[arg(long, default_value = "1h", value_parser = parse_ttl)]
+    ttl: i64,
+
+    /// Tenant (`tenant` claim) for multi-tenant services; sign with that tenant's secret
+    #[arg(long)]
+    tenant: Option<String>,
+
+    #[command(flatten)]
+    key: KeyArgs,
+
+    #[command(flatten)]
+    claims: ClaimArgs,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// The JWT to verify
+    token: String,
+
+    #[command(flatten)]
+    key: KeyArgs,
+
+    /// JWKS file with the verification keys, instead of a shared secret
+    #[arg(long, conflicts_with_all = ["secret", "secret_file"])]
+    jwks_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    claims: ClaimArgs,
+
+    /// Clock skew tolerated for `exp`, `nbf` and `iat`, in seconds
+    #[arg(long)]
+    leeway: Option<u64>,
+}
+
+#[derive(Args)]
+struct KeyArgs {
+    /// HMAC secret, as passed to the generated `start_server`
+    #[arg(long, conflicts_with = "secret_file")]
+    secret: Option<String>,
+
+    /// File containing the HMAC secret; a trailing newline is ignored
+    #[arg(long)]
+    secret_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ClaimArgs {
+    /// Issuer (`iss`) to stamp or require
+    #[arg(long)]
+    issuer: Option<String>,
+
+    /// Audience (`aud`) to stamp or require; may be repeated
+    #[arg(long)]
+    audience: Vec<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TokenKind {
+    User,
+    System,
+}
+
+impl From<TokenKind> for TokenType {
+    fn from(kind: TokenKind) -> Self {
+        match kind {
+            TokenKind::User => TokenType::User,
+            TokenKind::System => TokenType::System,
+        }
+    }
+}
+
+pub fn run(command: TokenCommand) -> Result<()> {
+    match command {
+        TokenCommand::Mint(args) => mint(args),
+        TokenCommand::Decode { token } => decode(&token),
+        TokenCommand::Verify(args) => verify(args),
+        TokenCommand::HashPassword { password } => {
+            println!("{}", auth_service::hash_password(&password)?);
+            Ok(())
+        }
+    }
+}
+
+fn mint(args: MintArgs) -> Result<()> {
+    println!("{}", mint_token(args)?);
+    Ok(())
+}
+
+fn mint_token(args: MintArgs) -> Result<String> {
+    let auth_service = AuthService::new(&args.key.secret()?).with_config(args.claims.config(None));
+
+    let mut claims = auth_service.standard_claims(&args.sub, args.roles, args.token_type.into(), 0);
+    claims.exp = chrono::Utc::now()
+        .timestamp()
+        .checked_add(args.ttl)
+        .and_then(|exp| usize::try_from(exp).ok())
+        .context("duration too large")?;
+    claims.tenant = args.tenant;
+    Ok(auth_service.sign(&claims)?)
+}
+
+fn decode(token: &str) -> Result<()> {
+    let (header, claims) = auth_service::inspect_token(token)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&serde_json::json!({ "header": header, "claims": claims }))?
+    );
+
+    if let Some(exp) = claims["exp"].as_i64() {
+        let remaining = exp - chrono::Utc::now().timestamp();
+        let expires = chrono::DateTime::from_timestamp(exp, 0)
+            .map(|time| time.to_rfc3339())
+            .unwrap_or_else(|| exp.to_string());
+        if remaining < 0 {
+            println!("Expired at {}", expires);
+        } else {
+            println!("Expires at {} (in {}s)", expires, remaining);
+        }
+    }
+    Ok(())
+}
+
+fn verify(args: VerifyArgs) -> Result<()> {
+    let claims = verify_token(&args)?;
+    println!("{}", serde_json::to_string_pretty(&claims)?);
+    println!("Token is valid");
+    Ok(())
+}
+
+fn verify_token(args: &VerifyArgs) -> Result<Claims> {
+    let auth_service = match &args.jwks_file {
+        Some(path) => AuthService::from_key_set(KeySet::from_jwks_file(path)?),
+        None => AuthService::new(&args.key.secret()?),
+    }
+    .with_config(args.claims.config(args.leeway));
+
+    auth_service
+        .verify_token(&args.token)
+        .map_err(|error| anyhow::anyhow!("Token is invalid ({}): {}", error.code(), error))
+}
+
+impl KeyArgs {
+    fn secret(&self) -> Result<Vec<u8>> {
+        match (&self.secret, &self.secret_file) {
+            (Some(secret), _) => Ok(secret.as_bytes().to_vec()),
+            (None, Some(path)) => {
+                let secret = std::fs::read_to_string(path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+                Ok(secret.trim_end_matches(['\r', '\n']).as_bytes().to_vec())
+            }
+            (None, None) => bail!("pass --secret or --secret-file"),
+        }
+    }
+}
+
+impl ClaimArgs {
+    fn config(&self, leeway: Option<u64>) -> AuthConfig {
+        let mut config = AuthConfig::new();
+        if let Some(issuer) = &self.issuer {
+            config = config.with_issuer(issuer);
+        }
+        for audience in &self.audience {
+            config = config.with_audience(audience);
+        }
+        if let Some(leeway) = leeway {
+            config = config.with_leeway(leeway);
+        }
+        config
+    }
+}
+
+fn parse_ttl(ttl: &str) -> Result<i64, String> {
+    let unit_at = ttl.find(|c: char| !c.is_ascii_digit()).unwrap_or(ttl.len());
+    let (amount, unit) = ttl.split_at(unit_at);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", ttl))?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit `{}`, use s, m, h or d", unit)),
+    };
+    amount
+        .checked_mul(seconds)
+        .ok_or_else(|| "duration too large".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct TokenCli {
+        #[command(subcommand)]
+        command: TokenCommand,
+    }
+
+    fn parse(args: &[&str]) -> TokenCommand {
+        TokenCli::try_parse_from(std::iter::once("token").chain(args.iter().copied()))
+            .unwrap()
+            .command
+    }
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("90"), Ok(90));
+        assert_eq!(parse_ttl("90s"), Ok(90));
+        assert_eq!(parse_ttl("30m"), Ok(30 * 60));
+        assert_eq!(parse_ttl("2h"), Ok(2 * 60 * 60));
+        assert_eq!(parse_ttl("7d"), Ok(7 * 24 * 60 * 60));
+
+        assert!(parse_ttl("2w").unwrap_err().contains("unknown unit `w`"));
+        assert!(parse_ttl("h").unwrap_err().contains("invalid duration"));
+        assert_eq!(
+            parse_ttl("99999999999999999d"),
+            Err("duration too large".to_string())
+        );
+    }
+
+    #[test]
+    fn test_mint_and_verify() {
+        let TokenCommand::Mint(args) = parse(&[
+            "mint",
+            "--sub",
+            "user123",
+            "--roles",
+            "admin,user",
+            "--type",
+            "system",
+            "--ttl",
+            "2h",
+            "--secret",
+            "test_secret",
+            "--issuer",
+            "https://auth.example.com",
+        ]) else {
+            unreachable!()
+        };
+        let token = mint_token(args).unwrap();
+
+        let TokenCommand::Verify(args) = parse(&[
+            "verify",
+            &token,
+            "--secret",
+            "test_secret",
+            "--issuer",
+            "https://auth.example.com",
+        ]) else {
+            unreachable!()
+        };
+        let claims = verify_token(&args).unwrap();
+        assert_eq!(claims.sub, "user123");
+        assert_eq!(claims.roles, vec!["admin", "user"]);
+        assert_eq!(claims.token_type, TokenType::System);
+        let lifetime = claims.exp as i64 - chrono::Utc::now().timestamp();
+        assert!((7190..=7200).contains(&lifetime));
+
+        let TokenCommand::Verify(args) = parse(&["verify", &token, "--secret", "other_secret"])
+        else {
+            unreachable!()
+        };
+        assert!(verify_token(&args).is_err());
+    }
+
+    #[test]
+    fn test_mint_rejects_overflowing_expiry() {
+        let TokenCommand::Mint(args) = parse(&[
+            "mint",
+            "--sub",
+            "user123",
+            "--ttl",
+            &i64::MAX.to_string(),
+            "--secret",
+            "test_secret",
+        ]) else {
+            unreachable!()
+        };
+        assert_eq!(
+            mint_token(args).unwrap_err().to_string(),
+            "duration too large"
+        );
+    }
+}
@@ -0,0 +1,849 @@
+use crate::{CodeGenerator, GeneratorOptions};
+use openapi_parser::{MediaType, OpenApiSpec, Operation, Schema};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use std::collections::HashMap;
+
+/// How a request or response body is represented on the wire
+///
+/// Variants are ordered by preference when an operation declares several media types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum BodyFormat {
+    Json,
+    Form,
+    Multipart,
+    Text,
+    Xml,
+    Binary,
+}
+
+impl BodyFormat {
+    pub(crate) fn from_media_type(media_type: &str) -> Self {
+        let essence = media_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+
+        match essence.as_str() {
+            "application/json" => BodyFormat::Json,
+            m if m.ends_with("+json") => BodyFormat::Json,
+            "application/x-www-form-urlencoded" => BodyFormat::Form,
+            "multipart/form-data" => BodyFormat::Multipart,
+            "application/xml" | "text/xml" => BodyFormat::Xml,
+            m if m.ends_with("+xml") => BodyFormat::Xml,
+            m if m.starts_with("text/") => BodyFormat::Text,
+            _ => BodyFormat::Binary,
+        }
+    }
+}
+
+/// Handler parameter extracting an operation's request body
+pub(crate) struct RequestBodyExtractor {
+    pub(crate) param: TokenStream,
+    /// Types the extractor relies on, emitted next to the handler
+    pub(crate) support: TokenStream,
+}
+
+/// Handler return type for an operation's success response
+pub(crate) struct ResponseBody {
+    pub(crate) return_type: TokenStream,
+    /// Extractor choosing the representation from `Accept`, for negotiated responses
+    pub(crate) accept_param: Option<TokenStream>,
+    pub(crate) support: TokenStream,
+}
+
+impl CodeGenerator {
+    /// Picks the media type a handler works with, preferring JSON and falling back in
+    /// `BodyFormat` order so the choice doesn't depend on map iteration order
+    pub(crate) fn preferred_media_type(
+        content: &HashMap<String, MediaType>,
+    ) -> Option<(&str, &MediaType)> {
+        Self::sorted_media_types(content).into_iter().next()
+    }
+
+    /// Declared media types in preference order
+    fn sorted_media_types(content: &HashMap<String, MediaType>) -> Vec<(&str, &MediaType)> {
+        let mut media_types: Vec<_> = content
+            .iter()
+            .map(|(name, media_type)| (name.as_str(), media_type))
+            .collect();
+        media_types.sort_by_key(|(name, _)| (BodyFormat::from_media_type(name), *name));
+        media_types
+    }
+
+    /// Format of a success response served as a single representation
+    pub(crate) fn response_body_format(operation: &Operation) -> Option<BodyFormat> {
+        Self::success_content(operation)
+            .filter(|content| content.len() == 1)
+            .and_then(Self::preferred_media_type)
+            .map(|(name, _)| BodyFormat::from_media_type(name))
+    }
+
+    /// Whether the request body needs `Content-Type` dispatch
+    fn has_negotiated_request(operation: &Operation) -> bool {
+        operation
+            .request_body
+            .as_ref()
+            .is_some_and(|body| body.content.len() > 1)
+    }
+
+    /// Whether the success response needs `Accept` dispatch
+    fn has_negotiated_response(operation: &Operation) -> bool {
+        Self::success_content(operation).is_some_and(|content| content.len() > 1)
+    }
+
+    /// Enum variant names for media types, e.g. `application/problem+json` -> `ProblemJson`
+    fn media_type_variants(media_types: &[&str]) -> Vec<proc_macro2::Ident> {
+        let essence = |media_type: &str| {
+            media_type
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        };
+        let short_name = |media_type: &str| match BodyFormat::from_media_type(media_type) {
+            BodyFormat::Form => "Form".to_string(),
+            BodyFormat::Multipart => "Multipart".to_string(),
+            _ => {
+                let essence = essence(media_type);
+                let subtype = essence.split('/').nth(1).unwrap_or(&essence);
+                Self::to_pascal_case(subtype)
+            }
+        };
+
+        media_types
+            .iter()
+            .map(|media_type| {
+                let name = short_name(media_type);
+                let clashes = media_types
+                    .iter()
+                    .filter(|other| short_name(other) == name)
+                    .count()
+                    > 1;
+                if clashes {
+                    format_ident!("{}", Self::to_pascal_case(&essence(media_type)))
+                } else {
+                    format_ident!("{}", name)
+                }
+            })
+            .collect()
+    }
+
+    /// Content of the lowest 2xx response that declares any
+    pub(crate) fn success_content(operation: &Operation) -> Option<&HashMap<String, MediaType>> {
+        let mut statuses: Vec<_> = operation
+            .responses
+            .iter()
+            .filter(|(status, _)| status.starts_with('2'))
+            .collect();
+        statuses.sort_by_key(|(status, _)| status.as_str());
+
+        statuses
+            .into_iter()
+            .find_map(|(_, response)| response.content.as_ref())
+    }
+
+    pub(crate) fn extract_request_body(
+        handler_name: &str,
+        operation: &Operation,
+        spec: &OpenApiSpec,
+        options: &GeneratorOptions,
+    ) -> Option<RequestBodyExtractor> {
+        let request_body = operation.request_body.as_ref()?;
+        if request_body.content.len() > 1 {
+            return Some(Self::generate_negotiated_request_body(
+                handler_name,
+                &request_body.content,
+                spec,
+                options,
+            ));
+        }
+
+        let (media_type_name, media_type) = Self::preferred_media_type(&request_body.content)?;
+        let body_type = |schema: &Schema| {
+            if options.split_read_write_models {
+                Self::schema_to_request_type(schema, spec)
+            } else {
+                Self::schema_to_type(schema, spec)
+            }
+        };
+
+        let mut support = TokenStream::new();
+        let param = match BodyFormat::from_media_type(media_type_name) {
+            BodyFormat::Json => {
+                let body_type = media_type
+                    .schema
+                    .as_ref()
+                    .map(body_type)
+                    .unwrap_or_else(|| quote! { serde_json::Value });
+                quote! { Json(payload): Json<#body_type> }
+            }
+            BodyFormat::Form => {
+                let body_type = media_type
+                    .schema
+                    .as_ref()
+                    .map(body_type)
+                    .unwrap_or_else(|| quote! { std::collections::HashMap<String, String> });
+                quote! { axum::Form(payload): axum::Form<#body_type> }
+            }
+            BodyFormat::Multipart => match &media_type.schema {
+                Some(schema) => {
+                    let struct_name =
+                        format_ident!("{}Multipart", Self::to_pascal_case(handler_name));
+                    support = Self::generate_multipart_struct(&struct_name, schema, spec, options);
+                    quote! { payload: #struct_name }
+                }
+                None => quote! { mut payload: axum::extract::Multipart },
+            },
+            BodyFormat::Text | BodyFormat::Xml => quote! { payload: String },
+            BodyFormat::Binary => quote! { payload: axum::body::Bytes },
+        };
+
+        Some(RequestBodyExtractor { param, support })
+    }
+
+    pub(crate) fn extract_response_type(
+        handler_name: &str,
+        operation: &Operation,
+        spec: &OpenApiSpec,
+    ) -> ResponseBody {
+        let single = |return_type| ResponseBody {
+            return_type,
+            accept_param: None,
+            support: TokenStream::new(),
+        };
+        let Some(content) = Self::success_content(operation) else {
+            return single(quote! { Json<serde_json::Value> });
+        };
+        if content.len() > 1 {
+            return Self::generate_negotiated_response(handler_name, content, spec);
+        }
+        let Some((media_type_name, media_type)) = Self::preferred_media_type(content) else {
+            return single(quote! { Json<serde_json::Value> });
+        };
+
+        let return_type = match BodyFormat::from_media_type(media_type_name) {
+            BodyFormat::Json | BodyFormat::Form | BodyFormat::Multipart => {
+                let body_type = media_type
+                    .schema
+                    .as_ref()
+                    .map(|schema| Self::schema_to_type(schema, spec))
+                    .unwrap_or_else(|| quote! { serde_json::Value });
+                quote! { Json<#body_type> }
+            }
+            BodyFormat::Text => quote! { String },
+            BodyFormat::Xml => quote! { Xml },
+            BodyFormat::Binary => quote! { FileDownload },
+        };
+
+        single(return_type)
+    }
+
+    /// Request body enum dispatching on `Content-Type`, rejecting undeclared types with 415
+    fn generate_negotiated_request_body(
+        handler_name: &str,
+        content: &HashMap<String, MediaType>,
+        spec: &OpenApiSpec,
+        options: &GeneratorOptions,
+    ) -> RequestBodyExtractor {
+        let enum_name = format_ident!("{}Body", Self::to_pascal_case(handler_name));
+        let media_types = Self::sorted_media_types(content);
+        let names: Vec<&str> = media_types.iter().map(|(name, _)| *name).collect();
+        let variants = Self::media_type_variants(&names);
+        let body_type = |media_type: &MediaType, fallback: TokenStream| match &media_type.schema {
+            Some(schema) if options.split_read_write_models => {
+                Self::schema_to_request_type(schema, spec)
+            }
+            Some(schema) => Self::schema_to_type(schema, spec),
+            None => fallback,
+        };
+
+        let mut support = TokenStream::new();
+        let mut enum_variants = Vec::new();
+        let mut arms = Vec::new();
+
+        for ((name, media_type), variant) in media_types.iter().zip(&variants) {
+            let (variant_type, extract) = match BodyFormat::from_media_type(name) {
+                BodyFormat::Json => {
+                    let body_type = body_type(media_type, quote! { serde_json::Value });
+                    (
+                        body_type.clone(),
+                        quote! {
+                            axum::Json::<#body_type>::from_request(req, state)
+                                .await
+                                .map(|axum::Json(body)| Self::#variant(body))
+                        },
+                    )
+                }
+                BodyFormat::Form => {
+                    let body_type = body_type(
+                        media_type,
+                        quote! { std::collections::HashMap<String, String> },
+                    );
+                    (
+                        body_type.clone(),
+                        quote! {
+                            axum::Form::<#body_type>::from_request(req, state)
+                                .await
+                                .map(|axum::Form(body)| Self::#variant(body))
+                        },
+                    )
+                }
+                BodyFormat::Multipart => {
+                    let variant_type = match &media_type.schema {
+                        Some(schema) => {
+                            let struct_name =
+                                format_ident!("{}Multipart", Self::to_pascal_case(handler_name));
+                            support.extend(Self::generate_multipart_struct(
+                                &struct_name,
+                                schema,
+                                spec,
+                                options,
+                            ));
+                            quote! { #struct_name }
+                        }
+                        None => quote! { axum::extract::Multipart },
+                    };
+                    (
+                        variant_type.clone(),
+                        quote! {
+                            <#variant_type>::from_request(req, state)
+                                .await
+                                .map(Self::#variant)
+                        },
+                    )
+                }
+                BodyFormat::Text | BodyFormat::Xml => (
+                    quote! { String },
+                    quote! { String::from_request(req, state).await.map(Self::#variant) },
+                ),
+                BodyFormat::Binary => (
+                    quote! { axum::body::Bytes },
+                    quote! {
+                        axum::body::Bytes::from_request(req, state)
+                            .await
+                            .map(Self::#variant)
+                    },
+                ),
+            };
+
+            enum_variants.push(quote! { #variant(#variant_type) });
+            arms.push(quote! {
+                content_type if media_type_matches(#name, content_type) => {
+                    #extract.map_err(IntoResponse::into_response)
+                }
+            });
+        }
+
+        let doc = format!(
+            " Request body of `{}`, selected by `Content-Type`",
+            handler_name
+        );
+        support.extend(quote! {
+            #[doc = #doc]
+            #[derive(Debug)]
+            pub enum #enum_name {
+                #(#enum_variants),*
+            }
+
+            impl<S> axum::extract::FromRequest<S> for #enum_name
+            where
+                S: Send + Sync,
+            {
+                type Rejection = axum::response::Response;
+
+                async fn from_request(
+                    req: axum::extract::Request,
+                    state: &S,
+                ) -> Result<Self, Self::Rejection> {
+                    use axum::response::IntoResponse;
+
+                    let content_type = request_content_type(req.headers());
+                    match content_type.as_str() {
+                        #(#arms)*
+                        _ => Err(axum::http::StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response()),
+                    }
+                }
+            }
+        });
+
+        RequestBodyExtractor {
+            param: quote! { payload: #enum_name },
+            support,
+        }
+    }
+
+    /// Response enum with one variant per representation, plus an extractor that picks the
+    /// representation from `Accept` and rejects unsatisfiable requests with 406
+    fn generate_negotiated_response(
+        handler_name: &str,
+        content: &HashMap<String, MediaType>,
+        spec: &OpenApiSpec,
+    ) -> ResponseBody {
+        let pascal_name = Self::to_pascal_case(handler_name);
+        let response_name = format_ident!("{}Response", pascal_name);
+        let media_name = format_ident!("{}MediaType", pascal_name);
+        let media_types = Self::sorted_media_types(content);
+        let names: Vec<&str> = media_types.iter().map(|(name, _)| *name).collect();
+        let variants = Self::media_type_variants(&names);
+
+        let mut enum_variants = Vec::new();
+        let mut render_arms = Vec::new();
+
+        for ((name, media_type), variant) in media_types.iter().zip(&variants) {
+            let schema_type = || {
+                media_type
+                    .schema
+                    .as_ref()
+                    .map(|schema| Self::schema_to_type(schema, spec))
+                    .unwrap_or_else(|| quote! { serde_json::Value })
+            };
+            let (variant_type, body) = match BodyFormat::from_media_type(name) {
+                BodyFormat::Json => (schema_type(), quote! { axum::Json(body) }),
+                BodyFormat::Form => (schema_type(), quote! { axum::Form(body) }),
+                BodyFormat::Text | BodyFormat::Xml => (quote! { String }, quote! { body }),
+                BodyFormat::Multipart | BodyFormat::Binary => {
+                    (quote! { axum::body::Body }, quote! { body })
+                }
+            };
+
+            enum_variants.push(quote! { #variant(#variant_type) });
+            render_arms.push(quote! {
+                Self::#variant(body) => (
+                    [(axum::http::header::CONTENT_TYPE, #name)],
+                    #body,
+                )
+                    .into_response()
+            });
+        }
+
+        let accept_arms = variants.iter().enumerate().map(|(idx, variant)| {
+            let idx = proc_macro2::Literal::usize_unsuffixed(idx);
+            quote! { Some(#idx) => Ok(Self::#variant) }
+        });
+        let media_doc = format!(" Representation of `{}` chosen from `Accept`", handler_name);
+        let response_doc = format!(
+            " Response of `{}` in each declared representation",
+            handler_name
+        );
+
+        let support = quote! {
+            #[doc = #media_doc]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub enum #media_name {
+                #(#variants),*
+            }
+
+            impl<S> axum::extract::FromRequestParts<S> for #media_name
+            where
+                S: Send + Sync,
+            {
+                type Rejection = axum::http::StatusCode;
+
+                async fn from_request_parts(
+                    parts: &mut axum::http::request::Parts,
+                    _state: &S,
+                ) -> Result<Self, Self::Rejection> {
+                    let accept = parts
+                        .headers
+                        .get(axum::http::header::ACCEPT)
+                        .and_then(|value| value.to_str().ok());
+
+                    match negotiate_media_type(accept, &[#(#names),*]) {
+                        #(#accept_arms,)*
+                        _ => Err(axum::http::StatusCode::NOT_ACCEPTABLE),
+                    }
+                }
+            }
+
+            #[doc = #response_doc]
+            pub enum #response_name {
+                #(#enum_variants),*
+            }
+
+            impl axum::response::IntoResponse for #response_name {
+                fn into_response(self) -> axum::response::Response {
+                    match self {
+                        #(#render_arms),*
+                    }
+                }
+            }
+        };
+
+        ResponseBody {
+            return_type: quote! { #response_name },
+            accept_param: Some(quote! { accept: #media_name }),
+            support,
+        }
+    }
+
+    /// Typed multipart/form-data body: binary properties become `UploadedFile` parts and the
+    /// remaining properties are parsed from their text parts
+    fn generate_multipart_struct(
+        struct_name: &proc_macro2::Ident,
+        schema: &Schema,
+        spec: &OpenApiSpec,
+        options: &GeneratorOptions,
+    ) -> TokenStream {
+        let mut properties = Vec::new();
+        let mut required = Vec::new();
+        Self::collect_properties(schema, &mut properties, &mut required, spec);
+        // Split request models never carry readOnly properties
+        if options.split_read_write_models {
+            properties.retain(|(_, property)| !property.is_read_only());
+        }
+
+        let mut fields = Vec::new();
+        let mut locals = Vec::new();
+        let mut arms = Vec::new();
+        let mut inits = Vec::new();
+
+        for (name, property) in &properties {
+            let ident = format_ident!("{}", Self::sanitize_identifier(name));
+            let items = match property {
+                Schema::Object {
+                    type_,
+                    items: Some(items),
+                    ..
+                } if type_.as_deref() == Some("array") => Some(items.as_ref()),
+                Schema::ArrayType { items, .. } => Some(items.as_ref()),
+                _ => None,
+            };
+
+            let part_schema = items.unwrap_or(property);
+            let (part_type, read_part) = if part_schema.format() == Some("binary") {
+                (
+                    quote! { UploadedFile },
+                    quote! { UploadedFile::from_field(field).await? },
+                )
+            } else {
+                let part_type = if options.split_read_write_models {
+                    Self::schema_to_request_type(part_schema, spec)
+                } else {
+                    Self::schema_to_type(part_schema, spec)
+                };
+                let parse = if matches!(
+                    part_schema.get_type(),
+                    Some("string" | "integer" | "number" | "boolean")
+                ) {
+                    quote! { text.parse() }
+                } else {
+                    quote! { serde_json::from_str(&text) }
+                };
+                (
+                    part_type,
+                    quote! {{
+                        let text = read_multipart_text(field).await?;
+                        #parse.map_err(|_| invalid_multipart_field(#name))?
+                    }},
+                )
+            };
+
+            if items.is_some() {
+                fields.push(quote! { pub #ident: Vec<#part_type> });
+                locals.push(quote! { let mut #ident = Vec::new(); });
+                arms.push(quote! { Some(#name) => #ident.push(#read_part), });
+                inits.push(quote! { #ident });
+            } else {
+                locals.push(quote! { let mut #ident = None; });
+                arms.push(quote! { Some(#name) => #ident = Some(#read_part), });
+                if required.contains(name) {
+                    fields.push(quote! { pub #ident: #part_type });
+                    inits.push(quote! {
+                        #ident: #ident.ok_or_else(|| invalid_multipart_field(#name))?
+                    });
+                } else {
+                    fields.push(quote! { pub #ident: Option<#part_type> });
+                    inits.push(quote! { #ident });
+                }
+            }
+        }
+
+        quote! {
+            #[derive(Debug)]
+            pub struct #struct_name {
+                #(#fields),*
+            }
+
+            impl<S> axum::extract::FromRequest<S> for #struct_name
+            where
+                S: Send + Sync,
+            {
+                type Rejection = axum::response::Response;
+
+                async fn from_request(
+                    req: axum::extract::Request,
+                    state: &S,
+                ) -> Result<Self, Self::Rejection> {
+                    use axum::response::IntoResponse;
+
+                    let mut multipart = axum::extract::Multipart::from_request(req, state)
+                        .await
+                        .map_err(IntoResponse::into_response)?;
+                    #(#locals)*
+
+                    while let Some(field) = multipart
+                        .next_field()
+                        .await
+                        .map_err(IntoResponse::into_response)?
+                    {
+                        match field.name() {
+                            #(#arms)*
+                            _ => {}
+                        }
+                    }
+
+                    Ok(Self {
+                        #(#inits),*
+                    })
+                }
+            }
+        }
+    }
+
+    /// Shared types for non-JSON bodies, emitted only when an operation needs them
+    pub(crate) fn generate_content_helpers(spec: &OpenApiSpec) -> TokenStream {
+        let operations: Vec<&Operation> = spec
+            .paths
+            .values()
+            .flat_map(|path_item| path_item.operations().map(|(_, op)| op))
+            .collect();
+
+        let uses_multipart = operations.iter().any(|op| {
+            op.request_body.as_ref().is_some_and(|body| {
+                body.content.iter().any(|(name, media_type)| {
+                    BodyFormat::from_media_type(name) == BodyFormat::Multipart
+                        && media_type.schema.is_some()
+                })
+            })
+        });
+        let uses_xml = operations
+            .iter()
+            .any(|op| Self::response_body_format(op) == Some(BodyFormat::Xml));
+        let uses_download = operations
+            .iter()
+            .any(|op| Self::response_body_format(op) == Some(BodyFormat::Binary));
+        let uses_request_negotiation = operations.iter().any(|op| Self::has_negotiated_request(op));
+        let uses_response_negotiation = operations
+            .iter()
+            .any(|op| Self::has_negotiated_response(op));
+
+        let mut output = TokenStream::new();
+
+        if uses_multipart {
+            output.extend(quote! {
+                /// A file part received in a multipart/form-data request
+                #[derive(Debug)]
+                pub struct UploadedFile {
+                    pub file_name: Option<String>,
+                    pub content_type: Option<String>,
+                    pub bytes: axum::body::Bytes,
+                }
+
+                impl UploadedFile {
+                    async fn from_field(
+                        field: axum::extract::multipart::Field<'_>,
+                    ) -> Result<Self, axum::response::Response> {
+                        use axum::response::IntoResponse;
+
+                        let file_name = field.file_name().map(str::to_string);
+                        let content_type = field.content_type().map(str::to_string);
+                        let bytes = field.bytes().await.map_err(IntoResponse::into_response)?;
+
+                        Ok(Self {
+                            file_name,
+                            content_type,
+                            bytes,
+                        })
+                    }
+                }
+
+                async fn read_multipart_text(
+                    field: axum::extract::multipart::Field<'_>,
+                ) -> Result<String, axum::response::Response> {
+                    use axum::response::IntoResponse;
+
+                    field.text().await.map_err(IntoResponse::into_response)
+                }
+
+                fn invalid_multipart_field(name: &str) -> axum::response::Response {
+                    use axum::response::IntoResponse;
+
+                    (
+                        axum::http::StatusCode::BAD_REQUEST,
+                        format!("missing or invalid multipart field `{}`", name),
+                    )
+                        .into_response()
+                }
+            });
+        }
+
+        if uses_xml {
+            output.extend(quote! {
+                /// XML response body
+                #[derive(Debug)]
+                pub struct Xml(pub String);
+
+                impl axum::response::IntoResponse for Xml {
+                    fn into_response(self) -> axum::response::Response {
+                        (
+                            [(axum::http::header::CONTENT_TYPE, "application/xml")],
+                            self.0,
+                        )
+                            .into_response()
+                    }
+                }
+            });
+        }
+
+        if uses_download {
+            output.extend(quote! {
+                /// Streaming file download response
+                pub struct FileDownload {
+                    pub content_type: String,
+                    pub file_name: Option<String>,
+                    pub body: axum::body::Body,
+                }
+
+                impl FileDownload {
+                    /// Streams a file from disk without buffering it in memory
+                    pub async fn from_path(
+                        path: impl AsRef<std::path::Path>,
+                        content_type: &str,
+                    ) -> std::io::Result<Self> {
+                        let path = path.as_ref();
+                        let file = tokio::fs::File::open(path).await?;
+
+                        Ok(Self {
+                            content_type: content_type.to_string(),
+                            file_name: path.file_name().map(|n| n.to_string_lossy().into_owned()),
+                            body: axum::body::Body::from_stream(
+                                tokio_util::io::ReaderStream::new(file),
+                            ),
+                        })
+                    }
+                }
+
+                impl axum::response::IntoResponse for FileDownload {
+                    fn into_response(self) -> axum::response::Response {
+                        use axum::http::{header, HeaderValue};
+
+                        let mut response = self.body.into_response();
+                        let headers = response.headers_mut();
+                        if let Ok(value) = HeaderValue::from_str(&self.content_type) {
+                            headers.insert(header::CONTENT_TYPE, value);
+                        }
+                        if let Some(file_name) = self.file_name {
+                            if let Ok(value) = HeaderValue::from_str(&content_disposition(&file_name)) {
+                                headers.insert(header::CONTENT_DISPOSITION, value);
+                            }
+                        }
+                        response
+                    }
+                }
+
+                /// `attachment` disposition with an ASCII `filename` fallback and the exact name
+                /// percent-encoded in `filename*` (RFC 6266, RFC 5987)
+                fn content_disposition(file_name: &str) -> String {
+                    let fallback: String = file_name
+                        .chars()
+                        .map(|c| match c {
+                            '"' | '\\' => '_',
+                            c if c == ' ' || c.is_ascii_graphic() => c,
+                            _ => '_',
+                        })
+                        .collect();
+                    let encoded: String = file_name
+                        .bytes()
+                        .map(|b| {
+                            if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+                                (b as char).to_string()
+                            } else {
+                                format!("%{:02X}", b)
+                            }
+                        })
+                        .collect();
+                    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+                }
+            });
+        }
+
+        if uses_request_negotiation || uses_response_negotiation {
+            output.extend(quote! {
+                /// Whether a media range such as `text/*` covers a concrete media type
+                fn media_type_matches(range: &str, media_type: &str) -> bool {
+                    let range = range.split(';').next().unwrap_or_default().trim();
+                    let media_type = media_type.split(';').next().unwrap_or_default().trim();
+
+                    match range.split_once('/') {
+                        Some(("*", "*")) => true,
+                        Some((main, "*")) => media_type
+                            .split_once('/')
+                            .is_some_and(|(other, _)| other.eq_ignore_ascii_case(main)),
+                        _ => range.eq_ignore_ascii_case(media_type),
+                    }
+                }
+            });
+        }
+
+        if uses_request_negotiation {
+            output.extend(quote! {
+                fn request_content_type(headers: &axum::http::HeaderMap) -> String {
+                    headers
+                        .get(axum::http::header::CONTENT_TYPE)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.split(';').next())
+                        .unwrap_or_default()
+                        .trim()
+                        .to_ascii_lowercase()
+                }
+            });
+        }
+
+        if uses_response_negotiation {
+            output.extend(quote! {
+                /// Index of the supported media type the `Accept` header prefers, honouring
+                /// q-values and letting the most specific matching range decide each type
+                fn negotiate_media_type(accept: Option<&str>, supported: &[&str]) -> Option<usize> {
+                    let Some(accept) = accept.filter(|accept| !accept.trim().is_empty()) else {
+                        return (!supported.is_empty()).then_some(0);
+                    };
+
+                    let mut best: Option<(usize, f32)> = None;
+                    for (idx, media_type) in supported.iter().enumerate() {
+                        let quality = accept
+                            .split(',')
+                            .filter(|range| media_type_matches(range, media_type))
+                            .map(|range| {
+                                let mut params = range.split(';');
+                                let specificity = match params.next().unwrap_or_default().trim() {
+                                    "*/*" => 0,
+                                    r if r.ends_with("/*") => 1,
+                                    _ => 2,
+                                };
+                                let quality = params
+                                    .filter_map(|param| param.trim().strip_prefix("q="))
+                                    .find_map(|q| q.trim().parse::<f32>().ok())
+                                    .unwrap_or(1.0);
+                                (specificity, quality)
+                            })
+                            .max_by_key(|(specificity, _)| *specificity)
+                            .map(|(_, quality)| quality)
+                            .unwrap_or(0.0);
+
+                        if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                            best = Some((idx, quality));
+                        }
+                    }
+
+                    best.map(|(idx, _)| idx)
+                }
+            });
+        }
+
+        output
+    }
+}
@@ -1,16 +1,143 @@
 use openapi_parser::OpenApiSpec;
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
+use thiserror::Error;
+
+mod content;
 
 pub struct CodeGenerator;
 
+/// Spec content that cannot be turned into a working server
+#[derive(Error, Debug)]
+pub enum GeneratorError {
+    #[error("Invalid default for {schema}.{property}: {reason}")]
+    InvalidDefault {
+        schema: String,
+        property: String,
+        reason: String,
+    },
+    #[error("Invalid x-auth-service: {0}")]
+    InvalidAuthService(String),
+}
+
+/// Options controlling the shape of the generated code
+#[derive(Debug, Clone, Default)]
+pub struct GeneratorOptions {
+    /// Generate a separate `<Name>Input` request model for schemas with `readOnly`/`writeOnly`
+    /// properties, leaving `<Name>` as the response model
+    pub split_read_write_models: bool,
+    /// Embed referenced `allOf` members as `#[serde(flatten)]` fields instead of copying their
+    /// properties; a single member becomes `base` with `Deref` to it
+    pub flatten_all_of: bool,
+}
+
+/// Fields of a generated struct: own properties plus `allOf` members embedded with flatten
+struct ModelFields {
+    properties: Vec<(String, openapi_parser::Schema)>,
+    required: Vec<String>,
+    bases: Vec<openapi_parser::Schema>,
+}
+
+/// Generated `SecurityPolicy` expression for a route
+struct SecurityLayer {
+    policy: TokenStream,
+    allows_anonymous: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ModelUsage {
+    Response,
+    Request,
+}
+
 impl CodeGenerator {
-    pub fn generate_axum_app(spec: &OpenApiSpec) -> TokenStream {
-        let structs = Self::generate_data_structures(spec);
-        let (routes, handlers) = Self::generate_routes_and_handlers(spec);
+    pub fn generate_axum_app(spec: &OpenApiSpec) -> Result<TokenStream, GeneratorError> {
+        Self::generate_axum_app_with_options(spec, &GeneratorOptions::default())
+    }
+
+    pub fn generate_axum_app_with_options(
+        spec: &OpenApiSpec,
+        options: &GeneratorOptions,
+    ) -> Result<TokenStream, GeneratorError> {
+        let jwks_file = spec
+            .auth_service
+            .as_ref()
+            .and_then(|auth| auth.jwks_file.as_deref());
+        let publishes_jwks = spec
+            .auth_service
+            .as_ref()
+            .is_some_and(|auth| auth.jwks == Some(true));
+        // The HS256 secret is never published, so the route would serve an empty set
+        if publishes_jwks && jwks_file.is_none() {
+            return Err(GeneratorError::InvalidAuthService(
+                "`jwks: true` needs a `jwksFile` with the keys to publish".to_string(),
+            ));
+        }
+
+        let structs = Self::generate_data_structures(spec, options)?;
+        let content_helpers = Self::generate_content_helpers(spec);
+        let (routes, handlers) = Self::generate_routes_and_handlers(spec, options);
         let auth_setup = Self::generate_auth_setup(spec);
+        let auth_config = spec
+            .auth_service
+            .as_ref()
+            .map(|_| quote! { .with_config(auth_config()) });
+        let introspection = spec
+            .auth_service
+            .as_ref()
+            .filter(|auth| auth.mode.as_deref() == Some("introspection"))
+            .map(|auth| {
+                let url = &auth.url;
+                quote! { .with_introspection(auth_service::TokenIntrospector::new(#url)) }
+            });
+        let policy = spec
+            .auth_service
+            .as_ref()
+            .and_then(|auth| auth.policy.as_ref())
+            .map(|path| quote! { .with_policy(auth_service::AuthorizationPolicy::from_file(#path)?) });
+        let request_signing = spec
+            .auth_service
+            .as_ref()
+            .and_then(|auth| auth.signing_keys.as_ref())
+            .map(|path| quote! { .with_request_signing(auth_service::RequestSigning::from_file(#path)?) });
+        let audit = spec
+            .auth_service
+            .as_ref()
+            .and_then(|auth| auth.audit.as_deref())
+            .map(|audit| match audit {
+                "tracing" => quote! { .with_audit_sink(auth_service::TracingAuditSink) },
+                path => quote! { .with_audit_sink(auth_service::JsonLinesAuditSink::open(#path)?) },
+            });
+        let tenants = spec
+            .auth_service
+            .as_ref()
+            .and_then(|auth| auth.tenants.as_ref())
+            .map(|path| quote! { .with_tenants(auth_service::Tenants::from_file(#path)?) });
+        let credentials = spec.auth_token_endpoint.as_ref().map(|endpoint| {
+            let path = &endpoint.credentials;
+            quote! { .with_credential_store(auth_service::FileCredentialStore::load(#path)?) }
+        });
+        let (service_type, with_claims) = match Self::claims_type(spec) {
+            Some(_) => (
+                quote! { AuthService<AppClaims> },
+                Some(quote! { .with_claims::<AppClaims>() }),
+            ),
+            None => (quote! { AuthService }, None),
+        };
+        let (policy_hooks, hook_registrations) = Self::generate_policy_hooks(spec);
+        let auth_imports = Self::auth_imports(spec);
+        let (key_param, new_auth_service) = match jwks_file {
+            Some(path) => (
+                TokenStream::new(),
+                quote! { AuthService::from_key_set(auth_service::KeySet::from_jwks_file(#path)?) },
+            ),
+            None => (
+                quote! { , jwt_secret: &str },
+                quote! { AuthService::new(jwt_secret.as_bytes()) },
+            ),
+        };
 
-        quote! {
+        Ok(quote! {
             //! Auto-generated Axum server from OpenAPI specification
             //! Generated by openapi-to-axum
 
@@ -21,100 +148,347 @@ impl CodeGenerator {
             };
             use serde::{Deserialize, Serialize};
             use std::net::SocketAddr;
-            use auth_service::{AuthService, AuthUser, user_auth_middleware, system_auth_middleware, require_roles};
+            use auth_service::{AuthService, AuthUser #(, #auth_imports)*};
 
             #structs
 
+            #content_helpers
+
             #handlers
 
             #auth_setup
 
+            #policy_hooks
+
             /// Create the Axum router with all generated routes
-            pub fn create_app(auth_service: AuthService) -> Router {
+            #[allow(deprecated)]
+            pub fn create_app(auth_service: #service_type) -> Router {
                 Router::new()
                     #routes
                     .with_state(auth_service)
             }
 
             /// Start the server on the given address
-            pub async fn start_server(addr: SocketAddr, jwt_secret: &str) -> Result<(), Box<dyn std::error::Error>> {
-                let auth_service = AuthService::new(jwt_secret.as_bytes());
+            pub async fn start_server(addr: SocketAddr #key_param) -> Result<(), Box<dyn std::error::Error>> {
+                let auth_service = #new_auth_service #auth_config #introspection #policy #request_signing #audit #tenants #credentials #with_claims #hook_registrations;
                 let app = create_app(auth_service);
 
                 println!("🚀 Server starting on {}", addr);
-                axum::serve(tokio::net::TcpListener::bind(addr).await?, app)
+                // Connection info gives audit events the client address
+                axum::serve(
+                    tokio::net::TcpListener::bind(addr).await?,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
                     .await
                     .map_err(|e| e.into())
             }
-        }
+        })
     }
 
+    /// Token validation settings taken from the spec's `x-auth-service` extension
     fn generate_auth_setup(spec: &OpenApiSpec) -> TokenStream {
-        if spec.auth_service.is_some() {
+        let Some(config) = &spec.auth_service else {
+            return TokenStream::new();
+        };
+
+        let issuer = match &config.issuer {
+            Some(issuer) => quote! { Some(#issuer.to_string()) },
+            None => quote! { None },
+        };
+        let audiences = config.audiences.iter().flatten();
+        let leeway = match config.leeway {
+            Some(leeway) => quote! { #leeway },
+            None => quote! { auth_service::AuthConfig::default().leeway_seconds },
+        };
+
+        let claims = Self::claims_type(spec).map(|claims| {
             quote! {
-                // Auth service configured via OpenAPI spec
+                /// Token claims declared by the `x-auth-service` extension
+                pub type AppClaims = auth_service::CustomClaims<#claims>;
             }
-        } else {
-            quote! {}
+        });
+
+        quote! {
+            #claims
+
+            /// Expected issuer, audiences and clock leeway for incoming tokens
+            pub fn auth_config() -> auth_service::AuthConfig {
+                auth_service::AuthConfig {
+                    issuer: #issuer,
+                    audiences: vec![#(#audiences.to_string()),*],
+                    leeway_seconds: #leeway,
+                }
+            }
+        }
+    }
+
+    /// Hook stubs for every `x-auth-policy` name and their registration on the auth service
+    fn generate_policy_hooks(spec: &OpenApiSpec) -> (TokenStream, TokenStream) {
+        let mut names: Vec<&str> = spec
+            .paths
+            .values()
+            .flat_map(|path_item| path_item.operations())
+            .filter_map(|(_, operation)| operation.auth_policy.as_deref())
+            .collect();
+        names.sort();
+        names.dedup();
+
+        let claims = match Self::claims_type(spec) {
+            Some(_) => quote! { AppClaims },
+            None => quote! { auth_service::Claims },
+        };
+        let mut hooks = TokenStream::new();
+        let mut registrations = TokenStream::new();
+        for name in names {
+            let ident = format_ident!("{}Policy", Self::to_pascal_case(name));
+            let doc = format!(
+                " Authorization hook for operations with `x-auth-policy: {}`",
+                name
+            );
+            let todo = format!("Implement the {} policy", name);
+            hooks.extend(quote! {
+                #[doc = #doc]
+                pub struct #ident;
+
+                #[auth_service::async_trait]
+                impl auth_service::AuthorizationHook<#claims> for #ident {
+                    async fn authorize(
+                        &self,
+                        claims: &#claims,
+                        params: &std::collections::HashMap<String, String>,
+                    ) -> Result<bool, auth_service::AuthError> {
+                        todo!(#todo)
+                    }
+                }
+            });
+            registrations.extend(quote! { .with_authorization_hook(#name, #ident) });
+        }
+
+        (hooks, registrations)
+    }
+
+    /// The operation's `x-auth-type`, falling back to the `x-auth-service` type
+    fn auth_type<'a>(
+        operation: &'a openapi_parser::Operation,
+        spec: &'a OpenApiSpec,
+    ) -> Option<&'a str> {
+        operation.auth_type.as_deref().or(spec
+            .auth_service
+            .as_ref()
+            .map(|config| config.auth_type.as_str()))
+    }
+
+    /// Imported token middleware for an auth type; signed requests use a qualified path instead
+    fn token_middleware(auth_type: Option<&str>) -> Option<&'static str> {
+        match auth_type {
+            Some("system") => Some("system_auth_middleware"),
+            Some("either") => Some("any_auth_middleware"),
+            Some("signed") => None,
+            _ => Some("user_auth_middleware"),
+        }
+    }
+
+    fn auth_middleware(operation: &openapi_parser::Operation, spec: &OpenApiSpec) -> TokenStream {
+        match Self::token_middleware(Self::auth_type(operation, spec)) {
+            Some(name) => {
+                let ident = format_ident!("{}", name);
+                quote! { #ident }
+            }
+            None => quote! { auth_service::signed_request_middleware },
+        }
+    }
+
+    /// Middlewares referenced by the generated routes, so unused ones are not imported
+    fn auth_imports(spec: &OpenApiSpec) -> Vec<proc_macro2::Ident> {
+        let mut names: Vec<&str> = Vec::new();
+        for (_, operation) in spec.paths.values().flat_map(|item| item.operations()) {
+            if !Self::auth_required(operation) || Self::security_policy(operation, spec).is_some() {
+                continue;
+            }
+            names.extend(Self::token_middleware(Self::auth_type(operation, spec)));
+            if operation
+                .auth_roles
+                .as_ref()
+                .is_some_and(|roles| !roles.is_empty())
+            {
+                names.push("require_roles");
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .map(|name| format_ident!("{}", name))
+            .collect()
+    }
+
+    /// Whether the operation requires authentication; naming a token type, permissions or a
+    /// policy implies it
+    fn auth_required(operation: &openapi_parser::Operation) -> bool {
+        operation.auth_required.unwrap_or(
+            operation.auth_type.is_some()
+                || operation.auth_permissions.is_some()
+                || operation.auth_policy.is_some(),
+        )
+    }
+
+    /// `SecurityPolicy` for the operation's effective `security` requirements
+    fn security_policy(
+        operation: &openapi_parser::Operation,
+        spec: &OpenApiSpec,
+    ) -> Option<SecurityLayer> {
+        // Standard `security` requirements apply when no auth extension other than
+        // `x-auth-type`, which selects the accepted token type, is used
+        let uses_auth_extensions = operation.auth_required.is_some()
+            || operation.auth_roles.is_some()
+            || operation.auth_permissions.is_some()
+            || operation.auth_policy.is_some()
+            || operation.auth_optional.is_some();
+        if uses_auth_extensions {
+            return None;
+        }
+
+        let requirements = spec.effective_security(operation)?;
+        if requirements.is_empty() {
+            return None;
+        }
+
+        let mut policy = quote! { auth_service::SecurityPolicy::new() };
+        let mut allows_anonymous = false;
+        for requirement in requirements {
+            if requirement.is_empty() {
+                allows_anonymous = true;
+                policy.extend(quote! { .allow_anonymous() });
+                continue;
+            }
+
+            let mut names: Vec<_> = requirement.keys().collect();
+            names.sort();
+            // Alternatives using schemes we cannot check are left out rather than waved through
+            let schemes: Option<Vec<TokenStream>> = names
+                .into_iter()
+                .map(|name| {
+                    Self::scheme_requirement(spec.security_scheme(name)?, &requirement[name])
+                })
+                .collect();
+            if let Some(schemes) = schemes {
+                policy.extend(quote! { .or(vec![#(#schemes),*]) });
+            }
+        }
+
+        match Self::auth_type(operation, spec) {
+            Some("user") => {
+                policy.extend(quote! { .with_token_type(auth_service::TokenType::User) })
+            }
+            Some("system") => {
+                policy.extend(quote! { .with_token_type(auth_service::TokenType::System) })
+            }
+            _ => {}
         }
+
+        Some(SecurityLayer {
+            policy,
+            allows_anonymous,
+        })
     }
 
-    fn generate_data_structures(spec: &OpenApiSpec) -> TokenStream {
+    /// Credential location of a security scheme; OAuth2 scopes are checked as roles
+    fn scheme_requirement(
+        scheme: &openapi_parser::SecurityScheme,
+        scopes: &[String],
+    ) -> Option<TokenStream> {
+        let scopes = quote! { vec![#(#scopes.to_string()),*] };
+        match scheme {
+            openapi_parser::SecurityScheme::Http { scheme, .. }
+                if scheme.eq_ignore_ascii_case("bearer") =>
+            {
+                Some(quote! { auth_service::SchemeRequirement::bearer(#scopes) })
+            }
+            openapi_parser::SecurityScheme::OAuth2 { .. }
+            | openapi_parser::SecurityScheme::OpenIdConnect { .. } => {
+                Some(quote! { auth_service::SchemeRequirement::bearer(#scopes) })
+            }
+            openapi_parser::SecurityScheme::ApiKey { name, in_ } => {
+                let location = match in_.as_str() {
+                    "header" => quote! { Header },
+                    "query" => quote! { Query },
+                    "cookie" => quote! { Cookie },
+                    _ => return None,
+                };
+                Some(quote! {
+                    auth_service::SchemeRequirement::api_key(
+                        auth_service::CredentialLocation::#location(#name.to_string()),
+                        #scopes,
+                    )
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Application claims schema referenced by `x-auth-service.claims`
+    fn claims_type(spec: &OpenApiSpec) -> Option<TokenStream> {
+        let reference = spec
+            .auth_service
+            .as_ref()?
+            .claims
+            .as_ref()?
+            .get_reference()?;
+        let type_name = reference.rsplit('/').next()?;
+        let ident = format_ident!("{}", Self::sanitize_identifier(type_name));
+        Some(quote! { #ident })
+    }
+
+    fn generate_data_structures(
+        spec: &OpenApiSpec,
+        options: &GeneratorOptions,
+    ) -> Result<TokenStream, GeneratorError> {
         let mut output = TokenStream::new();
 
         if let Some(components) = &spec.components {
             for (name, schema) in &components.schemas {
-                let struct_tokens = Self::schema_to_struct(name, schema, spec);
+                let struct_tokens = Self::schema_to_struct(name, schema, spec, options)?;
                 output.extend(struct_tokens);
             }
         }
 
-        output
+        Ok(output)
     }
 
     fn schema_to_struct(
         name: &str,
         schema: &openapi_parser::Schema,
         spec: &OpenApiSpec,
-    ) -> TokenStream {
+        options: &GeneratorOptions,
+    ) -> Result<TokenStream, GeneratorError> {
         let struct_name = format_ident!("{}", Self::sanitize_identifier(name));
 
-        match schema {
+        let tokens = match schema {
             // Handle allOf - merge all schemas
             openapi_parser::Schema::AllOf { all_of } => {
-                let mut all_properties = Vec::new();
-                let mut all_required = Vec::new();
-
-                for sub_schema in all_of {
-                    Self::collect_properties(
-                        sub_schema,
-                        &mut all_properties,
-                        &mut all_required,
-                        spec,
-                    );
-                }
-
-                let fields: Vec<TokenStream> = all_properties
-                    .iter()
-                    .map(|(field_name, field_schema)| {
-                        let field_ident =
-                            format_ident!("{}", Self::sanitize_identifier(field_name));
-                        let field_type = Self::schema_to_type(field_schema, spec);
-
-                        if all_required.contains(field_name) {
-                            quote! { pub #field_ident: #field_type }
-                        } else {
-                            quote! { pub #field_ident: Option<#field_type> }
-                        }
-                    })
-                    .collect();
+                let fields = if options.flatten_all_of {
+                    Self::collect_flattened_fields(all_of, spec)
+                } else {
+                    let mut all_properties = Vec::new();
+                    let mut all_required = Vec::new();
+
+                    for sub_schema in all_of {
+                        Self::collect_properties(
+                            sub_schema,
+                            &mut all_properties,
+                            &mut all_required,
+                            spec,
+                        );
+                    }
 
-                quote! {
-                    #[derive(Debug, Deserialize, Serialize)]
-                    pub struct #struct_name {
-                        #(#fields),*
+                    ModelFields {
+                        properties: all_properties,
+                        required: all_required,
+                        bases: Vec::new(),
                     }
-                }
+                };
+
+                Self::generate_models(name, schema, &fields, spec, options)?
             }
             // Handle oneOf - generate enum with variants
             openapi_parser::Schema::OneOf {
@@ -137,7 +511,7 @@ impl CodeGenerator {
                 if let Some(disc) = discriminator {
                     let tag = &disc.property_name;
                     quote! {
-                        #[derive(Debug, Deserialize, Serialize)]
+                        #[derive(Debug, Clone, Deserialize, Serialize)]
                         #[serde(tag = #tag)]
                         pub enum #struct_name {
                             #(#variants),*
@@ -145,7 +519,7 @@ impl CodeGenerator {
                     }
                 } else {
                     quote! {
-                        #[derive(Debug, Deserialize, Serialize)]
+                        #[derive(Debug, Clone, Deserialize, Serialize)]
                         #[serde(untagged)]
                         pub enum #struct_name {
                             #(#variants),*
@@ -169,7 +543,7 @@ impl CodeGenerator {
                     .collect();
 
                 quote! {
-                    #[derive(Debug, Deserialize, Serialize)]
+                    #[derive(Debug, Clone, Deserialize, Serialize)]
                     #[serde(untagged)]
                     pub enum #struct_name {
                         #(#variants),*
@@ -188,43 +562,23 @@ impl CodeGenerator {
                 required,
                 ..
             } => {
-                if let Some(props) = properties {
-                    let fields: Vec<TokenStream> = props
+                let fields = ModelFields {
+                    properties: properties
                         .iter()
+                        .flatten()
                         .map(|(field_name, field_schema)| {
-                            let field_ident =
-                                format_ident!("{}", Self::sanitize_identifier(field_name));
-                            let field_type = Self::schema_to_type(field_schema, spec);
-
-                            let is_required = required
-                                .as_ref()
-                                .map(|r| r.contains(field_name))
-                                .unwrap_or(false);
-
-                            if is_required {
-                                quote! { pub #field_ident: #field_type }
-                            } else {
-                                quote! { pub #field_ident: Option<#field_type> }
-                            }
+                            (field_name.clone(), field_schema.clone())
                         })
-                        .collect();
+                        .collect(),
+                    required: required.clone().unwrap_or_default(),
+                    bases: Vec::new(),
+                };
 
-                    quote! {
-                        #[derive(Debug, Deserialize, Serialize)]
-                        pub struct #struct_name {
-                            #(#fields),*
-                        }
-                    }
-                } else {
-                    quote! {
-                        #[derive(Debug, Deserialize, Serialize)]
-                        pub struct #struct_name {}
-                    }
-                }
+                Self::generate_models(name, schema, &fields, spec, options)?
             }
             // Handle references
             openapi_parser::Schema::Reference { ref_ } => {
-                let type_name = ref_.split('/').last().unwrap_or("Value");
+                let type_name = ref_.rsplit('/').next().unwrap_or("Value");
                 let ident = format_ident!("{}", Self::sanitize_identifier(type_name));
                 quote! {
                     pub type #struct_name = #ident;
@@ -232,27 +586,405 @@ impl CodeGenerator {
             }
             _ => {
                 quote! {
-                    #[derive(Debug, Deserialize, Serialize)]
+                    #[derive(Debug, Clone, Deserialize, Serialize)]
                     pub struct #struct_name {}
                 }
             }
+        };
+        Ok(tokens)
+    }
+
+    /// Splits `allOf` members into referenced schemas embedded via flatten and inline properties
+    fn collect_flattened_fields(
+        all_of: &[openapi_parser::Schema],
+        spec: &OpenApiSpec,
+    ) -> ModelFields {
+        let mut properties = Vec::new();
+        let mut required = Vec::new();
+        let mut bases = Vec::new();
+
+        for sub_schema in all_of {
+            if sub_schema.is_reference() {
+                bases.push(sub_schema.clone());
+            } else {
+                Self::collect_properties(sub_schema, &mut properties, &mut required, spec);
+            }
+        }
+
+        // A referenced member can only be reused as-is if no other member makes one of its
+        // optional properties required; otherwise its properties are copied in and merged
+        let mut flattened = Vec::new();
+        for base in bases {
+            let mut base_properties = Vec::new();
+            let mut base_required = Vec::new();
+            Self::collect_properties(&base, &mut base_properties, &mut base_required, spec);
+
+            let tightened = base_properties
+                .iter()
+                .any(|(name, _)| required.contains(name) && !base_required.contains(name));
+            if tightened {
+                Self::collect_properties(&base, &mut properties, &mut required, spec);
+            } else {
+                flattened.push(base);
+            }
+        }
+
+        ModelFields {
+            properties,
+            required,
+            bases: flattened,
+        }
+    }
+
+    fn generate_models(
+        name: &str,
+        schema: &openapi_parser::Schema,
+        fields: &ModelFields,
+        spec: &OpenApiSpec,
+        options: &GeneratorOptions,
+    ) -> Result<TokenStream, GeneratorError> {
+        if !options.split_read_write_models || !Self::has_read_write_split(schema, spec) {
+            return Self::generate_struct(name, schema, fields, spec, ModelUsage::Response);
+        }
+
+        // Responses never carry writeOnly properties, requests never carry readOnly ones
+        let response_fields = ModelFields {
+            properties: fields
+                .properties
+                .iter()
+                .filter(|(_, p)| !p.is_write_only())
+                .cloned()
+                .collect(),
+            required: fields.required.clone(),
+            bases: fields.bases.clone(),
+        };
+        let request_fields = ModelFields {
+            properties: fields
+                .properties
+                .iter()
+                .filter(|(_, p)| !p.is_read_only())
+                .cloned()
+                .collect(),
+            required: fields.required.clone(),
+            bases: fields.bases.clone(),
+        };
+
+        let response_model =
+            Self::generate_struct(name, schema, &response_fields, spec, ModelUsage::Response)?;
+        let request_model = Self::generate_struct(
+            &Self::request_model_name(name),
+            schema,
+            &request_fields,
+            spec,
+            ModelUsage::Request,
+        )?;
+
+        Ok(quote! {
+            #response_model
+            #request_model
+        })
+    }
+
+    fn has_read_write_split(schema: &openapi_parser::Schema, spec: &OpenApiSpec) -> bool {
+        let mut properties = Vec::new();
+        let mut required = Vec::new();
+        Self::collect_properties(schema, &mut properties, &mut required, spec);
+
+        properties
+            .iter()
+            .any(|(_, p)| p.is_read_only() || p.is_write_only())
+    }
+
+    fn request_model_name(name: &str) -> String {
+        format!("{}Input", name)
+    }
+
+    fn generate_struct(
+        name: &str,
+        schema: &openapi_parser::Schema,
+        model: &ModelFields,
+        spec: &OpenApiSpec,
+        usage: ModelUsage,
+    ) -> Result<TokenStream, GeneratorError> {
+        let struct_name = format_ident!("{}", Self::sanitize_identifier(name));
+        let struct_docs =
+            Self::generate_docs(schema.title(), schema.description(), &schema.examples());
+        let field_type_of = |field_schema: &openapi_parser::Schema| match usage {
+            ModelUsage::Response => Self::schema_to_type(field_schema, spec),
+            ModelUsage::Request => Self::schema_to_request_type(field_schema, spec),
+        };
+
+        let mut fields = Vec::new();
+        let mut default_fns = Vec::new();
+        let mut default_inits = Vec::new();
+        let mut conversions = Vec::new();
+
+        for base in &model.bases {
+            let base_type = field_type_of(base);
+            let base_ident = if model.bases.len() == 1 {
+                format_ident!("base")
+            } else {
+                format_ident!("{}", Self::to_snake_case(&base_type.to_string()))
+            };
+
+            fields.push(quote! {
+                #[serde(flatten)]
+                pub #base_ident: #base_type
+            });
+            conversions.push(quote! {
+                impl From<#struct_name> for #base_type {
+                    fn from(value: #struct_name) -> Self {
+                        value.#base_ident
+                    }
+                }
+            });
+
+            if model.bases.len() == 1 {
+                conversions.push(quote! {
+                    impl std::ops::Deref for #struct_name {
+                        type Target = #base_type;
+
+                        fn deref(&self) -> &Self::Target {
+                            &self.base
+                        }
+                    }
+
+                    impl std::ops::DerefMut for #struct_name {
+                        fn deref_mut(&mut self) -> &mut Self::Target {
+                            &mut self.base
+                        }
+                    }
+                });
+            }
+        }
+
+        for (field_name, field_schema) in &model.properties {
+            let field_ident = format_ident!("{}", Self::sanitize_identifier(field_name));
+            let field_type = field_type_of(field_schema);
+            let field_docs = Self::generate_docs(
+                field_schema.title(),
+                field_schema.description(),
+                &field_schema.examples(),
+            );
+            let deprecated = if field_schema.is_deprecated() {
+                quote! { #[deprecated] }
+            } else {
+                quote! {}
+            };
+
+            // A field with a default is never absent after deserialization, so it is not optional
+            if let Some(default) = field_schema.default_value().filter(|v| !v.is_null()) {
+                let default_fn = format_ident!(
+                    "default_{}_{}",
+                    Self::to_snake_case(name),
+                    Self::to_snake_case(field_name)
+                );
+                let default_fn_name = default_fn.to_string();
+                let default_expr =
+                    Self::default_value_expr(default, field_schema).map_err(|reason| {
+                        GeneratorError::InvalidDefault {
+                            schema: name.to_string(),
+                            property: field_name.clone(),
+                            reason,
+                        }
+                    })?;
+
+                default_fns.push(quote! {
+                    fn #default_fn() -> #field_type {
+                        #default_expr
+                    }
+                });
+                default_inits.push(quote! { #field_ident: #default_fn() });
+                fields.push(quote! {
+                    #field_docs
+                    #deprecated
+                    #[serde(default = #default_fn_name)]
+                    pub #field_ident: #field_type
+                });
+            } else if model.required.contains(field_name) {
+                fields.push(quote! {
+                    #field_docs
+                    #deprecated
+                    pub #field_ident: #field_type
+                });
+            } else {
+                fields.push(quote! {
+                    #field_docs
+                    #deprecated
+                    pub #field_ident: Option<#field_type>
+                });
+            }
+        }
+
+        // Only emit `impl Default` when every field can be filled from the spec
+        let default_impl = if !fields.is_empty() && default_inits.len() == fields.len() {
+            quote! {
+                #[allow(deprecated)]
+                impl Default for #struct_name {
+                    fn default() -> Self {
+                        Self {
+                            #(#default_inits),*
+                        }
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        Ok(quote! {
+            #struct_docs
+            #[derive(Debug, Clone, Deserialize, Serialize)]
+            pub struct #struct_name {
+                #(#fields),*
+            }
+
+            #(#default_fns)*
+
+            #default_impl
+
+            #(#conversions)*
+        })
+    }
+
+    fn generate_docs(
+        title: Option<&str>,
+        description: Option<&str>,
+        examples: &[&serde_json::Value],
+    ) -> TokenStream {
+        let mut lines: Vec<String> = Vec::new();
+
+        if let Some(title) = title {
+            lines.push(title.to_string());
+        }
+        if let Some(description) = description {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.extend(description.trim_end().lines().map(str::to_string));
+        }
+        for example in examples {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push(format!("Example: `{}`", example));
+        }
+
+        let lines = lines.iter().map(|line| format!(" {}", line));
+        quote! { #(#[doc = #lines])* }
+    }
+
+    /// Expression building a default of the type `schema_to_type` gives `schema`, or why the
+    /// value does not fit that type
+    fn default_value_expr(
+        value: &serde_json::Value,
+        schema: &openapi_parser::Schema,
+    ) -> Result<TokenStream, String> {
+        use openapi_parser::Schema;
+        use serde_json::Value;
+
+        let mismatch = |expected: &str| Err(format!("expected {}, got `{}`", expected, value));
+        let (type_, items) = match schema {
+            Schema::Reference { ref_ } => {
+                return Err(format!("cannot build a default of the referenced {}", ref_));
+            }
+            Schema::Object { type_, items, .. } => (type_.as_deref(), items.as_deref()),
+            // Without `items` a simple `array` is generated as `serde_json::Value`
+            Schema::SimpleType { type_, .. } if type_ != "array" => (Some(type_.as_str()), None),
+            Schema::SimpleType { .. } => (None, None),
+            Schema::ArrayType { items, .. } => (Some("array"), Some(items.as_ref())),
+            Schema::AllOf { .. }
+            | Schema::OneOf { .. }
+            | Schema::AnyOf { .. }
+            | Schema::Not { .. } => (None, None),
+        };
+
+        match (type_, value) {
+            (Some("string"), Value::String(s)) => Ok(quote! { #s.to_string() }),
+            (Some("string"), _) => mismatch("a string"),
+            (Some("integer"), Value::Number(n)) if n.is_i64() => {
+                let n = n.as_i64().unwrap_or_default();
+                if schema.format() == Some("int32") && i32::try_from(n).is_err() {
+                    return mismatch("a 32-bit integer");
+                }
+                let lit = proc_macro2::Literal::i64_unsuffixed(n);
+                Ok(quote! { #lit })
+            }
+            (Some("integer"), _) => mismatch("an integer"),
+            (Some("number"), Value::Number(n)) => {
+                let lit = proc_macro2::Literal::f64_unsuffixed(n.as_f64().unwrap_or_default());
+                Ok(quote! { #lit })
+            }
+            (Some("number"), _) => mismatch("a number"),
+            (Some("boolean"), Value::Bool(b)) => Ok(quote! { #b }),
+            (Some("boolean"), _) => mismatch("a boolean"),
+            (Some("array"), Value::Array(values)) => {
+                let values = values
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| match items {
+                        Some(items) => Self::default_value_expr(value, items)
+                            .map_err(|reason| format!("item {}: {}", index, reason)),
+                        None => {
+                            let json = Self::json_tokens(value);
+                            Ok(quote! { serde_json::json!(#json) })
+                        }
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(quote! { vec![#(#values),*] })
+            }
+            (Some("array"), _) => mismatch("an array"),
+            // Everything else is generated as `serde_json::Value`
+            _ => {
+                let json = Self::json_tokens(value);
+                Ok(quote! { serde_json::json!(#json) })
+            }
+        }
+    }
+
+    /// `serde_json::json!` input for a value
+    fn json_tokens(value: &serde_json::Value) -> TokenStream {
+        use serde_json::Value;
+
+        match value {
+            Value::Null => quote! { null },
+            Value::Bool(b) => quote! { #b },
+            Value::Number(n) => {
+                let lit = match (n.as_i64(), n.as_u64()) {
+                    (Some(n), _) => proc_macro2::Literal::i64_unsuffixed(n),
+                    (_, Some(n)) => proc_macro2::Literal::u64_unsuffixed(n),
+                    _ => proc_macro2::Literal::f64_unsuffixed(n.as_f64().unwrap_or_default()),
+                };
+                quote! { #lit }
+            }
+            Value::String(s) => quote! { #s },
+            Value::Array(values) => {
+                let values = values.iter().map(Self::json_tokens);
+                quote! { [#(#values),*] }
+            }
+            Value::Object(members) => {
+                let keys = members.keys();
+                let values = members.values().map(Self::json_tokens);
+                quote! { { #(#keys: #values),* } }
+            }
         }
     }
 
     fn get_variant_name(schema: &openapi_parser::Schema, idx: usize) -> proc_macro2::Ident {
         match schema {
             openapi_parser::Schema::Reference { ref_ } => {
-                let name = ref_.split('/').last().unwrap_or("Value");
+                let name = ref_.rsplit('/').next().unwrap_or("Value");
                 format_ident!("{}", Self::sanitize_identifier(name))
             }
             _ => format_ident!("Variant{}", idx),
         }
     }
 
+    #[allow(clippy::only_used_in_recursion)]
     fn schema_to_type(schema: &openapi_parser::Schema, spec: &OpenApiSpec) -> TokenStream {
         match schema {
             openapi_parser::Schema::Reference { ref_ } => {
-                let type_name = ref_.split('/').last().unwrap_or("Value");
+                let type_name = ref_.rsplit('/').next().unwrap_or("Value");
                 let ident = format_ident!("{}", Self::sanitize_identifier(type_name));
                 quote! { #ident }
             }
@@ -344,14 +1076,44 @@ impl CodeGenerator {
         }
     }
 
-    fn resolve_schema_ref(ref_: &str, spec: &OpenApiSpec) -> Option<openapi_parser::Schema> {
-        if let Some(components) = &spec.components {
-            if ref_.starts_with("#/components/schemas/") {
-                let schema_name = ref_.trim_start_matches("#/components/schemas/");
-                return components.schemas.get(schema_name).cloned();
+    /// Like `schema_to_type`, but references to split models resolve to their request variant
+    fn schema_to_request_type(schema: &openapi_parser::Schema, spec: &OpenApiSpec) -> TokenStream {
+        match schema {
+            openapi_parser::Schema::Reference { ref_ } => {
+                let split = Self::resolve_schema_ref(ref_, spec)
+                    .map(|resolved| Self::has_read_write_split(&resolved, spec))
+                    .unwrap_or(false);
+                if split {
+                    let type_name = ref_.rsplit('/').next().unwrap_or("Value");
+                    let ident = format_ident!(
+                        "{}",
+                        Self::request_model_name(&Self::sanitize_identifier(type_name))
+                    );
+                    quote! { #ident }
+                } else {
+                    Self::schema_to_type(schema, spec)
+                }
             }
+            openapi_parser::Schema::Object {
+                type_,
+                items: Some(items),
+                ..
+            } if type_.as_deref() == Some("array") => {
+                let item_type = Self::schema_to_request_type(items, spec);
+                quote! { Vec<#item_type> }
+            }
+            openapi_parser::Schema::ArrayType { items, .. } => {
+                let item_type = Self::schema_to_request_type(items, spec);
+                quote! { Vec<#item_type> }
+            }
+            _ => Self::schema_to_type(schema, spec),
         }
-        None
+    }
+
+    fn resolve_schema_ref(ref_: &str, spec: &OpenApiSpec) -> Option<openapi_parser::Schema> {
+        let components = spec.components.as_ref()?;
+        let schema_name = ref_.strip_prefix("#/components/schemas/")?;
+        components.schemas.get(schema_name).cloned()
     }
 
     fn collect_properties(
@@ -395,16 +1157,40 @@ impl CodeGenerator {
         }
     }
 
-    fn generate_routes_and_handlers(spec: &OpenApiSpec) -> (TokenStream, TokenStream) {
+    fn generate_routes_and_handlers(
+        spec: &OpenApiSpec,
+        options: &GeneratorOptions,
+    ) -> (TokenStream, TokenStream) {
         let mut routes = TokenStream::new();
         let mut handlers = TokenStream::new();
 
         for (path, path_item) in &spec.paths {
-            let (path_routes, path_handlers) = Self::generate_path_routes(path, path_item, spec);
+            let (path_routes, path_handlers) =
+                Self::generate_path_routes(path, path_item, spec, options);
             routes.extend(path_routes);
             handlers.extend(path_handlers);
         }
 
+        // Publish the auth service's public signing keys
+        if spec
+            .auth_service
+            .as_ref()
+            .is_some_and(|config| config.jwks == Some(true))
+        {
+            routes.extend(quote! {
+                .route("/.well-known/jwks.json", get(auth_service::jwks_handler))
+            });
+        }
+
+        // Issue tokens to the users and clients of the credentials file
+        if let Some(endpoint) = &spec.auth_token_endpoint {
+            let path = endpoint.path.as_deref().unwrap_or("/auth/token");
+            let expires_in = endpoint.expires_in.unwrap_or(3600);
+            routes.extend(quote! {
+                .route(#path, post(auth_service::token_endpoint(#expires_in)))
+            });
+        }
+
         (routes, handlers)
     }
 
@@ -412,27 +1198,28 @@ impl CodeGenerator {
         path: &str,
         path_item: &openapi_parser::PathItem,
         spec: &OpenApiSpec,
+        options: &GeneratorOptions,
     ) -> (TokenStream, TokenStream) {
         let mut routes = TokenStream::new();
         let mut handlers = TokenStream::new();
 
         if let Some(op) = &path_item.get {
-            let (route, handler) = Self::generate_route("get", path, op, spec);
+            let (route, handler) = Self::generate_route("get", path, op, spec, options);
             routes.extend(route);
             handlers.extend(handler);
         }
         if let Some(op) = &path_item.post {
-            let (route, handler) = Self::generate_route("post", path, op, spec);
+            let (route, handler) = Self::generate_route("post", path, op, spec, options);
             routes.extend(route);
             handlers.extend(handler);
         }
         if let Some(op) = &path_item.put {
-            let (route, handler) = Self::generate_route("put", path, op, spec);
+            let (route, handler) = Self::generate_route("put", path, op, spec, options);
             routes.extend(route);
             handlers.extend(handler);
         }
         if let Some(op) = &path_item.delete {
-            let (route, handler) = Self::generate_route("delete", path, op, spec);
+            let (route, handler) = Self::generate_route("delete", path, op, spec, options);
             routes.extend(route);
             handlers.extend(handler);
         }
@@ -445,6 +1232,7 @@ impl CodeGenerator {
         path: &str,
         operation: &openapi_parser::Operation,
         spec: &OpenApiSpec,
+        options: &GeneratorOptions,
     ) -> (TokenStream, TokenStream) {
         let handler_name = if let Some(op_id) = &operation.operation_id {
             format_ident!("{}", Self::sanitize_identifier(op_id))
@@ -454,23 +1242,56 @@ impl CodeGenerator {
 
         let method_ident = format_ident!("{}", method);
         let path_params = Self::extract_path_parameters(operation, spec);
-        let request_body = Self::extract_request_body(operation, spec);
-        let response_type = Self::extract_response_type(operation, spec);
+        let request_body =
+            Self::extract_request_body(&handler_name.to_string(), operation, spec, options);
+        let response = Self::extract_response_type(&handler_name.to_string(), operation, spec);
 
-        // Check if auth is required
-        let auth_required = operation.auth_required.unwrap_or(false);
+        let security = Self::security_policy(operation, spec);
+        let auth_required = security.is_none() && Self::auth_required(operation);
         let auth_roles = operation.auth_roles.clone().unwrap_or_default();
+        let auth_optional = !auth_required && operation.auth_optional.unwrap_or(false);
+        let needs_auth_user = auth_required
+            || security
+                .as_ref()
+                .is_some_and(|security| !security.allows_anonymous);
+        let allows_anonymous = auth_optional
+            || security
+                .as_ref()
+                .is_some_and(|security| security.allows_anonymous);
 
         // Build handler parameters
         let mut handler_params = Vec::new();
 
-        // Add auth user if required
-        if auth_required {
+        // Add auth user if required, or as an `Option` when anonymous requests are accepted
+        let auth_user = match Self::claims_type(spec) {
+            Some(_) => quote! { AuthUser<AppClaims> },
+            None => quote! { AuthUser },
+        };
+        if needs_auth_user {
+            handler_params.push(quote! {
+                auth_user: #auth_user
+            });
+            if spec
+                .auth_service
+                .as_ref()
+                .is_some_and(|auth| auth.tenants.is_some())
+            {
+                // Platform tokens carry no tenant
+                handler_params.push(quote! {
+                    auth_tenant: Option<auth_service::Tenant>
+                });
+            }
+        } else if allows_anonymous {
             handler_params.push(quote! {
-                auth_user: AuthUser
+                auth_user: Option<#auth_user>
             });
         }
 
+        // Add the negotiated response representation
+        if let Some(accept_param) = response.accept_param {
+            handler_params.push(accept_param);
+        }
+
         // Add path parameters
         if !path_params.is_empty() {
             let path_param_names: Vec<_> = path_params
@@ -495,11 +1316,13 @@ impl CodeGenerator {
         }
 
         // Add request body
-        if let Some(body_type) = request_body.clone() {
-            handler_params.push(quote! {
-                Json(payload): Json<#body_type>
-            });
-        }
+        let body_support = match request_body {
+            Some(body) => {
+                handler_params.push(body.param);
+                body.support
+            }
+            None => TokenStream::new(),
+        };
 
         let params_combined = if handler_params.is_empty() {
             quote! {}
@@ -510,36 +1333,69 @@ impl CodeGenerator {
 
         // Generate route with middleware if auth is required
         let route = if auth_required {
-            if auth_roles.is_empty() {
-                quote! {
-                    .route(#path, #method_ident(#handler_name)
-                        .layer(middleware::from_fn_with_state(auth_service.clone(), user_auth_middleware)))
+            let auth_middleware = Self::auth_middleware(operation, spec);
+            // The hook runs innermost, after the cheaper role and permission checks
+            let mut checks = Vec::new();
+            if let Some(policy) = &operation.auth_policy {
+                checks.push(quote! { auth_service::require_policy(#policy) });
+            }
+            if !auth_roles.is_empty() {
+                checks.push(quote! { require_roles(vec![#(#auth_roles.to_string()),*]) });
+            }
+            if let Some(permissions) = &operation.auth_permissions {
+                let all = permissions.all();
+                if !all.is_empty() {
+                    checks.push(quote! { auth_service::require_all_permissions(vec![#(#all.to_string()),*]) });
                 }
-            } else {
-                let roles_vec = auth_roles;
-                quote! {
-                    .route(#path, #method_ident(#handler_name)
-                        .layer(middleware::from_fn_with_state(auth_service.clone(), user_auth_middleware))
-                        .layer(middleware::from_fn_with_state(auth_service.clone(), require_roles(vec![#(#roles_vec.to_string()),*]))))
+                let any = permissions.any();
+                if !any.is_empty() {
+                    checks.push(quote! { auth_service::require_any_permission(vec![#(#any.to_string()),*]) });
                 }
             }
+            // Layers run outermost first, so authentication is added last
+            quote! {
+                .route(#path, #method_ident(#handler_name)
+                    #(.layer(middleware::from_fn_with_state(auth_service.clone(), #checks)))*
+                    .layer(middleware::from_fn_with_state(auth_service.clone(), #auth_middleware)))
+            }
+        } else if auth_optional {
+            quote! {
+                .route(#path, #method_ident(#handler_name)
+                    .layer(middleware::from_fn_with_state(auth_service.clone(), auth_service::optional_auth_middleware)))
+            }
+        } else if let Some(security) = security {
+            let policy = security.policy;
+            quote! {
+                .route(#path, #method_ident(#handler_name)
+                    .layer(middleware::from_fn_with_state(auth_service.clone(), auth_service::require_security(#policy))))
+            }
         } else {
             quote! {
                 .route(#path, #method_ident(#handler_name))
             }
         };
 
-        let handler = if let Some(return_type) = response_type {
-            quote! {
-                async fn #handler_name(#params_combined) -> Json<#return_type> {
-                    todo!("Implement {} {}", #method, #path)
-                }
-            }
+        let handler_docs = Self::generate_docs(
+            operation.summary.as_deref(),
+            operation.description.as_deref(),
+            &[],
+        );
+        let deprecated = if operation.deprecated.unwrap_or(false) {
+            quote! { #[deprecated] }
         } else {
-            quote! {
-                async fn #handler_name(#params_combined) -> Json<serde_json::Value> {
-                    todo!("Implement {} {}", #method, #path)
-                }
+            quote! {}
+        };
+
+        let response_type = response.return_type;
+        let response_support = response.support;
+        let handler = quote! {
+            #body_support
+            #response_support
+
+            #handler_docs
+            #deprecated
+            async fn #handler_name(#params_combined) -> #response_type {
+                todo!("Implement {} {}", #method, #path)
             }
         };
 
@@ -568,46 +1424,37 @@ impl CodeGenerator {
         params
     }
 
-    fn extract_request_body(
-        operation: &openapi_parser::Operation,
-        spec: &OpenApiSpec,
-    ) -> Option<TokenStream> {
-        eprintln!("DEBUG: Checking request body for operation");
-        if let Some(request_body) = &operation.request_body {
-            eprintln!("DEBUG: Found request_body");
-            if let Some(media_type) = request_body.content.get("application/json") {
-                eprintln!("DEBUG: Found application/json media type");
-                if let Some(schema) = &media_type.schema {
-                    eprintln!("DEBUG: Found schema, converting to type");
-                    let schema_type = Self::schema_to_type(schema, spec);
-                    return Some(schema_type);
-                }
-            }
-        }
-        eprintln!("DEBUG: No request body found");
-        None
+    fn sanitize_identifier(ident: &str) -> String {
+        ident.replace(|c: char| !c.is_alphanumeric() && c != '_', "_")
     }
 
-    fn extract_response_type(
-        operation: &openapi_parser::Operation,
-        spec: &OpenApiSpec,
-    ) -> Option<TokenStream> {
-        for (status, response) in &operation.responses {
-            if status.starts_with('2') {
-                if let Some(content) = &response.content {
-                    if let Some(media_type) = content.get("application/json") {
-                        if let Some(schema) = &media_type.schema {
-                            return Some(Self::schema_to_type(schema, spec));
-                        }
-                    }
+    fn to_snake_case(name: &str) -> String {
+        let mut snake = String::new();
+        for (i, c) in Self::sanitize_identifier(name).chars().enumerate() {
+            if c.is_uppercase() {
+                if i > 0 && !snake.ends_with('_') {
+                    snake.push('_');
                 }
+                snake.extend(c.to_lowercase());
+            } else {
+                snake.push(c);
             }
         }
-        None
+        snake
     }
 
-    fn sanitize_identifier(ident: &str) -> String {
-        ident.replace(|c: char| !c.is_alphanumeric() && c != '_', "_")
+    fn to_pascal_case(name: &str) -> String {
+        Self::sanitize_identifier(name)
+            .split('_')
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let mut chars = part.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            })
+            .collect()
     }
 
     fn sanitize_path(path: &str) -> String {
@@ -707,4 +1554,1035 @@ impl CodeGenerator {
 //     }
 // }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Generated code with all whitespace removed, for checks independent of formatting
+    fn compact(code: &str) -> String {
+        code.split_whitespace().collect()
+    }
+
+    fn contains(code: &str, snippet: &str) -> bool {
+        compact(code).contains(&compact(snippet))
+    }
+
+    fn generate(yaml: &str) -> String {
+        let spec = OpenApiSpec::from_yaml(yaml).expect("valid spec");
+        let tokens = CodeGenerator::generate_axum_app(&spec).expect("generates");
+        let file = syn::parse2(tokens).expect("valid Rust");
+        prettyplease::unparse(&file)
+    }
+
+    #[test]
+    fn test_schema_metadata_is_carried_into_structs() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+paths:
+  /settings:
+    get:
+      operationId: "getSettings"
+      summary: "Read the settings"
+      deprecated: true
+      responses:
+        "200":
+          description: "Settings"
+components:
+  schemas:
+    Settings:
+      type: object
+      description: "Client settings"
+      properties:
+        retries:
+          type: integer
+          format: int32
+          description: "How many times to retry"
+          default: 3
+        mode:
+          type: string
+          default: "fast"
+          example: "slow"
+        legacy:
+          type: boolean
+          default: false
+          deprecated: true
+        pageSize:
+          type: integer
+          default: 20
+"#,
+        );
+
+        assert!(contains(&output, "/// Client settings"));
+        assert!(contains(&output, "/// How many times to retry"));
+        assert!(contains(&output, "/// Example: `\"slow\"`"));
+        assert!(contains(
+            &output,
+            "#[serde(default = \"default_settings_retries\")]"
+        ));
+        assert!(contains(&output, "pub retries: i32"));
+        assert!(contains(&output, "fn default_settings_mode() -> String"));
+        assert!(contains(&output, "\"fast\".to_string()"));
+        assert!(contains(&output, "fn default_settings_page_size() -> i64"));
+        assert!(contains(&output, "impl Default for Settings"));
+        assert!(contains(
+            &output,
+            "#[deprecated] #[serde(default = \"default_settings_legacy\")]"
+        ));
+        assert!(contains(
+            &output,
+            "/// Read the settings #[deprecated] async fn getSettings"
+        ));
+        // Deprecated items are still wired up without the server warning about itself
+        assert!(contains(
+            &output,
+            "#[allow(deprecated)] impl Default for Settings"
+        ));
+        assert!(contains(&output, "#[allow(deprecated)] pub fn create_app"));
+    }
+
+    #[test]
+    fn test_no_default_impl_without_all_defaults() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+paths: {}
+components:
+  schemas:
+    Counter:
+      type: object
+      required: [name]
+      properties:
+        name:
+          type: string
+        count:
+          type: integer
+          default: 0
+"#,
+        );
+
+        assert!(contains(&output, "pub name: String"));
+        assert!(contains(&output, "pub count: i64"));
+        assert!(!contains(&output, "impl Default for Counter"));
+    }
+
+    #[test]
+    fn test_defaults_are_checked_against_their_type() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+paths: {}
+components:
+  schemas:
+    Filter:
+      type: object
+      properties:
+        offset:
+          type: integer
+          default: -1
+        tags:
+          type: array
+          items:
+            type: string
+          default: ["open", "urgent"]
+        extra:
+          type: object
+          default: {"sort": "asc", "limit": 10}
+"#,
+        );
+
+        assert!(contains(&output, "fn default_filter_tags() -> Vec<String>"));
+        assert!(contains(
+            &output,
+            "vec![\"open\".to_string(), \"urgent\".to_string()]"
+        ));
+        assert!(contains(&output, "serde_json::json!"));
+        assert!(!contains(&output, "expect"));
+
+        let invalid = |schema: &str| {
+            let spec = OpenApiSpec::from_yaml(&format!(
+                "openapi: \"3.0.0\"\ninfo:\n  title: \"Test API\"\n  version: \"1.0.0\"\npaths: {{}}\ncomponents:\n  schemas:\n    Settings:\n      type: object\n      properties:\n        value: {}\n",
+                schema
+            ))
+            .expect("valid spec");
+            CodeGenerator::generate_axum_app(&spec)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            invalid(r#"{type: integer, default: "three"}"#),
+            "Invalid default for Settings.value: expected an integer, got `\"three\"`"
+        );
+        assert!(invalid("{type: integer, format: int32, default: 3000000000}").contains("32-bit"));
+        assert!(
+            invalid("{type: array, items: {type: boolean}, default: [true, 1]}").contains("item 1")
+        );
+    }
+
+    #[test]
+    fn test_split_read_write_models() {
+        let spec = OpenApiSpec::from_yaml(
+            r##"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+paths:
+  /tasks:
+    post:
+      operationId: "createTask"
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: "#/components/schemas/Task"
+      responses:
+        "201":
+          description: "Created"
+          content:
+            application/json:
+              schema:
+                $ref: "#/components/schemas/Task"
+  /tasks/import:
+    post:
+      operationId: "importTask"
+      requestBody:
+        content:
+          multipart/form-data:
+            schema:
+              type: object
+              properties:
+                task:
+                  $ref: "#/components/schemas/Task"
+                source:
+                  type: string
+                  readOnly: true
+      responses:
+        "204":
+          description: "Imported"
+components:
+  schemas:
+    Task:
+      type: object
+      required: [id, title]
+      properties:
+        id:
+          type: string
+          readOnly: true
+        title:
+          type: string
+        secret:
+          type: string
+          writeOnly: true
+"##,
+        )
+        .unwrap();
+        let options = GeneratorOptions {
+            split_read_write_models: true,
+            ..Default::default()
+        };
+        let tokens =
+            CodeGenerator::generate_axum_app_with_options(&spec, &options).expect("generates");
+        let file = syn::parse2(tokens).expect("valid Rust");
+        let output = prettyplease::unparse(&file);
+
+        let task = output.split("pub struct Task {").nth(1).unwrap();
+        let task = &task[..task.find('}').unwrap()];
+        assert!(contains(task, "pub id: String"));
+        assert!(!contains(task, "secret"));
+
+        let input = output.split("pub struct TaskInput {").nth(1).unwrap();
+        let input = &input[..input.find('}').unwrap()];
+        assert!(!contains(input, "pub id"));
+        assert!(contains(input, "pub secret: Option<String>"));
+
+        assert!(contains(&output, "Json(payload): Json<TaskInput>"));
+        assert!(contains(&output, "-> Json<Task>"));
+
+        let multipart = output
+            .split("pub struct ImportTaskMultipart {")
+            .nth(1)
+            .unwrap();
+        let multipart = &multipart[..multipart.find('}').unwrap()];
+        assert!(contains(multipart, "pub task: Option<TaskInput>"));
+        assert!(!contains(multipart, "source"));
+    }
+
+    #[test]
+    fn test_flatten_all_of_members() {
+        let spec = OpenApiSpec::from_yaml(
+            r##"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+paths: {}
+components:
+  schemas:
+    Task:
+      type: object
+      required: [id]
+      properties:
+        id:
+          type: string
+        assigneeId:
+          type: string
+    UrgentTask:
+      allOf:
+        - $ref: "#/components/schemas/Task"
+        - type: object
+          required: [dueBy]
+          properties:
+            dueBy:
+              type: string
+    AssignedTask:
+      allOf:
+        - $ref: "#/components/schemas/Task"
+        - type: object
+          required: [assigneeId]
+"##,
+        )
+        .unwrap();
+        let options = GeneratorOptions {
+            flatten_all_of: true,
+            ..Default::default()
+        };
+        let tokens =
+            CodeGenerator::generate_axum_app_with_options(&spec, &options).expect("generates");
+        let file = syn::parse2(tokens).expect("valid Rust");
+        let output = prettyplease::unparse(&file);
+
+        assert!(contains(
+            &output,
+            "#[serde(flatten)] pub base: Task, pub dueBy: String"
+        ));
+        assert!(contains(&output, "impl From<UrgentTask> for Task"));
+        assert!(contains(&output, "impl std::ops::Deref for UrgentTask"));
+
+        // `assigneeId` is optional on Task, so AssignedTask can't reuse it and copies the fields
+        let assigned = output.split("pub struct AssignedTask {").nth(1).unwrap();
+        let assigned = &assigned[..assigned.find('}').unwrap()];
+        assert!(!contains(assigned, "flatten"));
+        assert!(contains(assigned, "pub assigneeId: String"));
+        assert!(contains(assigned, "pub id: String"));
+    }
+
+    #[test]
+    fn test_non_json_content_types() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+paths:
+  /login:
+    post:
+      operationId: "login"
+      requestBody:
+        content:
+          application/x-www-form-urlencoded:
+            schema:
+              type: object
+              properties:
+                username:
+                  type: string
+      responses:
+        "200":
+          description: "Greeting"
+          content:
+            text/plain:
+              schema:
+                type: string
+  /attachments:
+    post:
+      operationId: "uploadAttachment"
+      requestBody:
+        content:
+          multipart/form-data:
+            schema:
+              type: object
+              required: [file]
+              properties:
+                file:
+                  type: string
+                  format: binary
+                retries:
+                  type: integer
+      responses:
+        "204":
+          description: "Stored"
+  /attachments/{id}:
+    get:
+      operationId: "downloadAttachment"
+      parameters:
+        - name: id
+          in: path
+          required: true
+          schema:
+            type: string
+      responses:
+        "200":
+          description: "File contents"
+          content:
+            application/octet-stream:
+              schema:
+                type: string
+                format: binary
+    put:
+      operationId: "replaceAttachment"
+      requestBody:
+        content:
+          application/octet-stream: {}
+      responses:
+        "200":
+          description: "Manifest"
+          content:
+            application/xml: {}
+"#,
+        );
+
+        assert!(contains(
+            &output,
+            "axum::Form(payload): axum::Form<serde_json::Value>"
+        ));
+        assert!(contains(&output, "async fn login("));
+        assert!(contains(&output, ") -> String {"));
+
+        assert!(contains(&output, "pub struct UploadAttachmentMultipart"));
+        assert!(contains(&output, "pub file: UploadedFile"));
+        assert!(contains(&output, "pub retries: Option<i64>"));
+        assert!(contains(&output, "payload: UploadAttachmentMultipart"));
+        assert!(contains(&output, "pub struct UploadedFile"));
+
+        assert!(contains(&output, "Path(id): Path<String>) -> FileDownload"));
+        assert!(contains(&output, "pub struct FileDownload"));
+        assert!(contains(&output, "filename*=UTF-8''"));
+        assert!(contains(&output, "payload: axum::body::Bytes) -> Xml"));
+        assert!(contains(&output, "pub struct Xml(pub String);"));
+    }
+
+    #[test]
+    fn test_content_negotiation() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+paths:
+  /reports:
+    post:
+      operationId: "createReport"
+      requestBody:
+        content:
+          application/json: {}
+          text/csv: {}
+      responses:
+        "201":
+          description: "Created"
+          content:
+            application/json: {}
+            application/problem+json: {}
+"#,
+        );
+
+        assert!(contains(
+            &output,
+            "pub enum CreateReportBody { Json(serde_json::Value), Csv(String)"
+        ));
+        assert!(contains(&output, "StatusCode::UNSUPPORTED_MEDIA_TYPE"));
+        assert!(contains(
+            &output,
+            "pub enum CreateReportMediaType { Json, ProblemJson"
+        ));
+        assert!(contains(&output, "StatusCode::NOT_ACCEPTABLE"));
+        assert!(contains(
+            &output,
+            "(axum::http::header::CONTENT_TYPE, \"application/problem+json\")"
+        ));
+        assert!(contains(
+            &output,
+            "async fn createReport(accept: CreateReportMediaType, payload: CreateReportBody"
+        ));
+        assert!(contains(&output, "fn negotiate_media_type("));
+        assert!(contains(&output, "fn request_content_type("));
+    }
+
+    #[test]
+    fn test_auth_config_from_extension() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "user"
+  issuer: "https://auth.example.com"
+  audiences: ["orders", "billing"]
+  leeway: 30
+paths: {}
+"#,
+        );
+
+        assert!(contains(
+            &output,
+            "auth_service::AuthConfig { issuer: Some(\"https://auth.example.com\".to_string()), audiences: vec![\"orders\".to_string(), \"billing\".to_string()], leeway_seconds: 30u64"
+        ));
+        assert!(contains(&output, ".with_config(auth_config());"));
+    }
+
+    #[test]
+    fn test_introspection_mode() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080/oauth/introspect"
+  type: "user"
+  mode: "introspection"
+paths: {}
+"#,
+        );
+
+        assert!(contains(&output, ".with_introspection("));
+        assert!(contains(
+            &output,
+            "\"http://localhost:8080/oauth/introspect\""
+        ));
+    }
+
+    #[test]
+    fn test_signed_requests() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "user"
+  signingKeys: "signing-keys.yaml"
+paths:
+  /internal/sync:
+    post:
+      operationId: "syncTasks"
+      x-auth-type: "signed"
+      responses:
+        "204":
+          description: "Synced"
+"#,
+        );
+
+        assert!(contains(&output, ".with_request_signing("));
+        assert!(contains(
+            &output,
+            "auth_service::RequestSigning::from_file(\"signing-keys.yaml\")?"
+        ));
+        assert!(contains(&output, "signed_request_middleware"));
+        assert!(contains(&output, "auth_user: AuthUser"));
+    }
+
+    #[test]
+    fn test_audit_sinks() {
+        let spec = |audit: &str| {
+            format!(
+                r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "user"
+  audit: "{}"
+paths: {{}}
+"#,
+                audit
+            )
+        };
+
+        let output = generate(&spec("tracing"));
+        assert!(contains(
+            &output,
+            ".with_audit_sink(auth_service::TracingAuditSink)"
+        ));
+        assert!(contains(
+            &output,
+            "into_make_service_with_connect_info::<SocketAddr>()"
+        ));
+
+        let output = generate(&spec("audit.jsonl"));
+        assert!(contains(
+            &output,
+            "auth_service::JsonLinesAuditSink::open(\"audit.jsonl\")?"
+        ));
+    }
+
+    #[test]
+    fn test_tenants() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "user"
+  tenants: "tenants.yaml"
+paths:
+  /tenants/{tenant}/tasks:
+    get:
+      operationId: "listTasks"
+      x-auth-required: true
+      parameters:
+        - name: tenant
+          in: path
+          required: true
+          schema:
+            type: string
+      responses:
+        "200":
+          description: "Tasks"
+"#,
+        );
+
+        assert!(contains(
+            &output,
+            ".with_tenants(auth_service::Tenants::from_file(\"tenants.yaml\")?)"
+        ));
+        assert!(contains(
+            &output,
+            "auth_tenant: Option<auth_service::Tenant>"
+        ));
+    }
+
+    #[test]
+    fn test_token_endpoint() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "user"
+x-auth-token-endpoint:
+  credentials: "credentials.yaml"
+  expiresIn: 900
+paths: {}
+"#,
+        );
+
+        assert!(contains(
+            &output,
+            ".route(\"/auth/token\", post(auth_service::token_endpoint(900i64)))"
+        ));
+        assert!(contains(
+            &output,
+            "auth_service::FileCredentialStore::load(\"credentials.yaml\")?"
+        ));
+    }
+
+    #[test]
+    fn test_permission_requirements() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "user"
+  policy: "policy.yaml"
+paths:
+  /tasks/{taskId}:
+    delete:
+      operationId: "deleteTask"
+      x-auth-permissions: ["tasks:delete"]
+      responses:
+        "204":
+          description: "Deleted"
+    put:
+      operationId: "updateTask"
+      x-auth-required: true
+      x-auth-permissions:
+        any: ["tasks:write", "tasks:write:own"]
+      responses:
+        "200":
+          description: "Updated"
+"#,
+        );
+
+        assert!(contains(
+            &output,
+            ".with_policy(auth_service::AuthorizationPolicy::from_file(\"policy.yaml\")?)"
+        ));
+        assert!(contains(&output, "auth_service::require_all_permissions("));
+        assert!(contains(&output, "vec![\"tasks:delete\".to_string()]"));
+        assert!(contains(&output, "auth_service::require_any_permission("));
+        assert!(contains(&output, "\"tasks:write:own\".to_string()"));
+        assert_eq!(output.matches("user_auth_middleware,\n").count(), 2);
+    }
+
+    #[test]
+    fn test_authorization_policy_hooks() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "user"
+paths:
+  /tasks/{taskId}:
+    put:
+      operationId: "updateTask"
+      x-auth-policy: "task_owner"
+      responses:
+        "200":
+          description: "Updated"
+    delete:
+      operationId: "deleteTask"
+      x-auth-policy: "task_owner"
+      responses:
+        "204":
+          description: "Deleted"
+"#,
+        );
+
+        assert_eq!(output.matches("pub struct TaskOwnerPolicy;").count(), 1);
+        assert!(contains(
+            &output,
+            "impl auth_service::AuthorizationHook<auth_service::Claims> for TaskOwnerPolicy"
+        ));
+        assert!(contains(
+            &output,
+            ".with_authorization_hook(\"task_owner\", TaskOwnerPolicy);"
+        ));
+        assert_eq!(
+            output
+                .matches("auth_service::require_policy(\"task_owner\")")
+                .count(),
+            2
+        );
+        assert!(contains(&output, "auth_user: AuthUser"));
+    }
+
+    #[test]
+    fn test_optional_auth() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "user"
+paths:
+  /tasks/{taskId}:
+    get:
+      operationId: "getTask"
+      x-auth-optional: true
+      responses:
+        "200":
+          description: "A task"
+    delete:
+      operationId: "deleteTask"
+      x-auth-required: true
+      x-auth-optional: true
+      responses:
+        "204":
+          description: "Deleted"
+"#,
+        );
+
+        assert!(contains(
+            &output,
+            "async fn getTask(auth_user: Option<AuthUser>)"
+        ));
+        assert!(contains(&output, "auth_service::optional_auth_middleware"));
+        // Requiring auth outright wins over the optional flag
+        assert!(contains(
+            &output,
+            "async fn deleteTask(auth_user: AuthUser)"
+        ));
+        assert_eq!(output.matches("optional_auth_middleware").count(), 1);
+    }
+
+    #[test]
+    fn test_custom_claims_type() {
+        let output = generate(
+            r##"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "user"
+  claims:
+    $ref: "#/components/schemas/TenantClaims"
+paths:
+  /me:
+    get:
+      operationId: "getMe"
+      x-auth-required: true
+      responses:
+        "200":
+          description: "OK"
+components:
+  schemas:
+    TenantClaims:
+      type: object
+      required: [tenant_id]
+      properties:
+        tenant_id:
+          type: string
+        email:
+          type: string
+"##,
+        );
+
+        assert!(contains(
+            &output,
+            "#[derive(Debug, Clone, Deserialize, Serialize)] pub struct TenantClaims {"
+        ));
+        assert!(contains(
+            &output,
+            "pub type AppClaims = auth_service::CustomClaims<TenantClaims>;"
+        ));
+        assert!(contains(
+            &output,
+            "pub fn create_app(auth_service: AuthService<AppClaims>) -> Router"
+        ));
+        assert!(contains(&output, ".with_claims::<AppClaims>()"));
+        assert!(contains(
+            &output,
+            "async fn getMe(auth_user: AuthUser<AppClaims>)"
+        ));
+    }
+
+    #[test]
+    fn test_auth_type_selects_middleware() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "system"
+paths:
+  /jobs:
+    get:
+      operationId: "listJobs"
+      x-auth-required: true
+      responses:
+        "200":
+          description: "OK"
+    post:
+      operationId: "createJob"
+      x-auth-type: "user"
+      x-auth-roles: ["admin"]
+      responses:
+        "201":
+          description: "Created"
+  /status:
+    get:
+      operationId: "getStatus"
+      x-auth-type: "either"
+      responses:
+        "200":
+          description: "OK"
+"#,
+        );
+
+        assert!(contains(
+            &output,
+            "get(listJobs).layer(middleware::from_fn_with_state(auth_service.clone(), system_auth_middleware"
+        ));
+        // Role checks are layered inside authentication, so they run on verified claims
+        let create_job = compact(&output);
+        let create_job = &create_job[create_job.find("post(createJob)").unwrap()..];
+        let roles = create_job
+            .find("require_roles(vec![\"admin\".to_string()])")
+            .unwrap();
+        assert!(roles < create_job.find("user_auth_middleware").unwrap());
+        assert!(contains(&output, "async fn getStatus(auth_user: AuthUser)"));
+        assert!(contains(
+            &output,
+            "auth_service.clone(), any_auth_middleware"
+        ));
+    }
+
+    #[test]
+    fn test_security_requirements() {
+        let output = generate(
+            r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+security:
+  - bearerAuth: []
+paths:
+  /jobs:
+    get:
+      operationId: "listJobs"
+      security:
+        - oauth: ["jobs:read"]
+        - apiKey: []
+          bearerAuth: []
+      responses:
+        "200":
+          description: "OK"
+  /feed:
+    get:
+      operationId: "getFeed"
+      security:
+        - {}
+      responses:
+        "200":
+          description: "OK"
+  /health:
+    get:
+      operationId: "health"
+      security: []
+      responses:
+        "200":
+          description: "OK"
+  /me:
+    get:
+      operationId: "getMe"
+      x-auth-type: system
+      responses:
+        "200":
+          description: "OK"
+components:
+  securitySchemes:
+    bearerAuth:
+      type: http
+      scheme: bearer
+    apiKey:
+      type: apiKey
+      name: X-API-Key
+      in: cookie
+    oauth:
+      type: oauth2
+      flows:
+        clientCredentials:
+          tokenUrl: "https://auth.example.com/token"
+          scopes:
+            "jobs:read": "Read jobs"
+"#,
+        );
+
+        assert!(contains(
+            &output,
+            "auth_service::SchemeRequirement::bearer(vec![\"jobs:read\""
+        ));
+        assert!(contains(
+            &output,
+            "auth_service::CredentialLocation::Cookie(\"X-API-Key\""
+        ));
+        assert!(contains(
+            &output,
+            ".or(vec![auth_service::SchemeRequirement::bearer(vec![])])"
+        ));
+        assert!(contains(&output, ".allow_anonymous()"));
+        assert!(contains(&output, "async fn listJobs(auth_user: AuthUser)"));
+        assert!(contains(&output, "async fn getMe(auth_user: AuthUser)"));
+        // Anonymous access still hands the handler any claims that were presented
+        assert!(contains(
+            &output,
+            "async fn getFeed(auth_user: Option<AuthUser>)"
+        ));
+        assert!(contains(&output, ".route(\"/health\", get(health))"));
+
+        // `x-auth-type` picks the token type of the operation's policy, and no token
+        // middleware is left to import
+        assert_eq!(
+            output
+                .matches(".with_token_type(auth_service::TokenType::System)")
+                .count(),
+            1
+        );
+        assert!(contains(
+            &output,
+            "use auth_service::{AuthService, AuthUser};"
+        ));
+    }
+
+    #[test]
+    fn test_jwks_route() {
+        let yaml = |jwks: &str| {
+            format!(
+                r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+x-auth-service:
+  url: "http://localhost:8080"
+  type: "user"{}
+paths: {{}}
+"#,
+                jwks
+            )
+        };
+
+        // The published keys are the ones the service verifies with
+        let output = generate(&yaml("\n  jwks: true\n  jwksFile: \"keys/jwks.json\""));
+        assert!(contains(
+            &output,
+            ".route(\"/.well-known/jwks.json\", get(auth_service::jwks_handler))"
+        ));
+        assert!(contains(
+            &output,
+            "pub async fn start_server(addr: SocketAddr) -> Result<(), Box<dyn std::error::Error>>"
+        ));
+        assert!(contains(
+            &output,
+            "let auth_service = AuthService::from_key_set(auth_service::KeySet::from_jwks_file(\"keys/jwks.json\")?"
+        ));
+
+        // Without a key file there is nothing to publish
+        let spec = OpenApiSpec::from_yaml(&yaml("\n  jwks: true")).expect("valid spec");
+        assert!(matches!(
+            CodeGenerator::generate_axum_app(&spec),
+            Err(GeneratorError::InvalidAuthService(_))
+        ));
+
+        let output = generate(&yaml(""));
+        assert!(!contains(&output, "jwks_handler"));
+        assert!(contains(
+            &output,
+            "let auth_service = AuthService::new(jwt_secret.as_bytes())"
+        ));
+    }
+}
+
 //  endregion
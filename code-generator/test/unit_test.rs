@@ -7,7 +7,7 @@ fn test_generate_from_taskmanager_spec() {
 
     let spec = OpenApiSpec::from_yaml(yaml_content).expect("Failed to parse taskmanager.yaml");
 
-    let generated = CodeGenerator::generate_axum_app(&spec);
+    let generated = CodeGenerator::generate_axum_app(&spec).expect("Failed to generate code");
     let output = generated.to_string();
 
     // Verify key structures are generated
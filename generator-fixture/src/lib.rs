@@ -0,0 +1,5 @@
+//! Servers generated from the example and fixture specs, compiled against `auth-service`.
+//! Building this crate checks that generated code type-checks, and `deny(deprecated)` that it
+//! uses deprecated items from the spec without warnings.
+
+include!(concat!(env!("OUT_DIR"), "/servers.rs"));
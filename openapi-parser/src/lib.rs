@@ -18,13 +18,52 @@ pub struct OpenApiSpec {
     pub components: Option<Components>,
     #[serde(rename = "x-auth-service")]
     pub auth_service: Option<AuthServiceConfig>,
+    #[serde(rename = "x-auth-token-endpoint")]
+    pub auth_token_endpoint: Option<TokenEndpointConfig>,
+    pub security: Option<Vec<SecurityRequirement>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AuthServiceConfig {
     pub url: String,
     #[serde(rename = "type")]
-    pub auth_type: String, // "user" or "system"
+    pub auth_type: String, // "user", "system", "either" or "signed"
+    // Serve `/.well-known/jwks.json` from the generated app; needs `jwks_file`
+    pub jwks: Option<bool>,
+    // JWKS file with the verification keys, used instead of the HS256 secret
+    #[serde(rename = "jwksFile")]
+    pub jwks_file: Option<String>,
+    // Expected `iss` claim of accepted tokens
+    pub issuer: Option<String>,
+    // Accepted `aud` claim values
+    pub audiences: Option<Vec<String>>,
+    // Clock skew tolerated when checking `exp`, `nbf` and `iat`, in seconds
+    pub leeway: Option<u64>,
+    // `$ref` to the schema of application claims carried next to the standard ones
+    pub claims: Option<Schema>,
+    // "jwt" (default) or "introspection" for opaque tokens checked against `url`
+    pub mode: Option<String>,
+    // Role inheritance and permission file loaded by the generated server at startup
+    pub policy: Option<String>,
+    // Shared keys of services calling operations with `x-auth-type: signed`
+    #[serde(rename = "signingKeys")]
+    pub signing_keys: Option<String>,
+    // Authentication audit log: "tracing", or the path of a JSON-lines file
+    pub audit: Option<String>,
+    // Per-tenant keys and role sets; handlers then receive the token's `Option<Tenant>`
+    pub tenants: Option<String>,
+}
+
+// OAuth2 token endpoint issuing tokens for password and client credentials grants
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TokenEndpointConfig {
+    // Users and clients file loaded by the generated server at startup
+    pub credentials: String,
+    // Route of the endpoint, `/auth/token` by default
+    pub path: Option<String>,
+    // Lifetime of issued access tokens in seconds, one hour by default
+    #[serde(rename = "expiresIn")]
+    pub expires_in: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -41,11 +80,27 @@ pub struct PathItem {
     pub delete: Option<Operation>,
 }
 
+impl PathItem {
+    /// Declared operations paired with their lowercase HTTP method
+    pub fn operations(&self) -> impl Iterator<Item = (&'static str, &Operation)> {
+        [
+            ("get", &self.get),
+            ("post", &self.post),
+            ("put", &self.put),
+            ("delete", &self.delete),
+        ]
+        .into_iter()
+        .filter_map(|(method, operation)| operation.as_ref().map(|op| (method, op)))
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Operation {
     #[serde(rename = "operationId")]
     pub operation_id: Option<String>,
     pub summary: Option<String>,
+    pub description: Option<String>,
+    pub deprecated: Option<bool>,
     pub parameters: Option<Vec<Parameter>>,
     #[serde(rename = "requestBody")]
     pub request_body: Option<RequestBody>,
@@ -54,6 +109,19 @@ pub struct Operation {
     pub auth_required: Option<bool>,
     #[serde(rename = "x-auth-roles")]
     pub auth_roles: Option<Vec<String>>,
+    // "user", "system", "either" or "signed"; defaults to the `x-auth-service` type
+    #[serde(rename = "x-auth-type")]
+    pub auth_type: Option<String>,
+    #[serde(rename = "x-auth-permissions")]
+    pub auth_permissions: Option<PermissionRequirement>,
+    // Name of an authorization hook called with the claims and path parameters
+    #[serde(rename = "x-auth-policy")]
+    pub auth_policy: Option<String>,
+    // Accept anonymous requests but read the claims of any token sent
+    #[serde(rename = "x-auth-optional")]
+    pub auth_optional: Option<bool>,
+    // Overrides the spec-level `security`; an empty list disables it
+    pub security: Option<Vec<SecurityRequirement>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -83,7 +151,79 @@ pub struct Response {
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Components {
+    #[serde(default)]
     pub schemas: HashMap<String, Schema>,
+    #[serde(rename = "securitySchemes")]
+    pub security_schemes: Option<HashMap<String, SecurityScheme>>,
+}
+
+// A list requires every permission; `any` accepts one of them
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum PermissionRequirement {
+    All(Vec<String>),
+    Rules {
+        #[serde(default)]
+        all: Vec<String>,
+        #[serde(default)]
+        any: Vec<String>,
+    },
+}
+
+impl PermissionRequirement {
+    pub fn all(&self) -> &[String] {
+        match self {
+            PermissionRequirement::All(all) | PermissionRequirement::Rules { all, .. } => all,
+        }
+    }
+
+    pub fn any(&self) -> &[String] {
+        match self {
+            PermissionRequirement::All(_) => &[],
+            PermissionRequirement::Rules { any, .. } => any,
+        }
+    }
+}
+
+// Scheme name to required scopes; all schemes of one requirement apply together
+pub type SecurityRequirement = HashMap<String, Vec<String>>;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum SecurityScheme {
+    #[serde(rename = "http")]
+    Http {
+        scheme: String,
+        #[serde(rename = "bearerFormat")]
+        bearer_format: Option<String>,
+    },
+    #[serde(rename = "apiKey")]
+    ApiKey {
+        name: String,
+        #[serde(rename = "in")]
+        in_: String,
+    },
+    #[serde(rename = "oauth2")]
+    OAuth2 { flows: HashMap<String, OAuthFlow> },
+    #[serde(rename = "openIdConnect")]
+    OpenIdConnect {
+        #[serde(rename = "openIdConnectUrl")]
+        open_id_connect_url: String,
+    },
+    #[serde(rename = "mutualTLS")]
+    MutualTls,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct OAuthFlow {
+    #[serde(rename = "authorizationUrl")]
+    pub authorization_url: Option<String>,
+    #[serde(rename = "tokenUrl")]
+    pub token_url: Option<String>,
+    #[serde(rename = "refreshUrl")]
+    pub refresh_url: Option<String>,
+    #[serde(default)]
+    pub scopes: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -118,6 +258,16 @@ pub enum Schema {
         format: Option<String>,
         #[serde(rename = "enum")]
         enum_values: Option<Vec<serde_json::Value>>,
+        title: Option<String>,
+        description: Option<String>,
+        default: Option<serde_json::Value>,
+        example: Option<serde_json::Value>,
+        examples: Option<Vec<serde_json::Value>>,
+        deprecated: Option<bool>,
+        #[serde(rename = "readOnly")]
+        read_only: Option<bool>,
+        #[serde(rename = "writeOnly")]
+        write_only: Option<bool>,
     },
     SimpleType {
         #[serde(rename = "type")]
@@ -165,6 +315,74 @@ impl Schema {
         }
     }
 
+    pub fn title(&self) -> Option<&str> {
+        match self {
+            Schema::Object { title, .. } => title.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        match self {
+            Schema::Object { description, .. } => description.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn default_value(&self) -> Option<&serde_json::Value> {
+        match self {
+            Schema::Object { default, .. } => default.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns `example` followed by every entry of `examples`
+    pub fn examples(&self) -> Vec<&serde_json::Value> {
+        match self {
+            Schema::Object {
+                example, examples, ..
+            } => example.iter().chain(examples.iter().flatten()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        matches!(
+            self,
+            Schema::Object {
+                deprecated: Some(true),
+                ..
+            }
+        )
+    }
+
+    pub fn format(&self) -> Option<&str> {
+        match self {
+            Schema::Object { format, .. } | Schema::SimpleType { format, .. } => format.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Schema::Object {
+                read_only: Some(true),
+                ..
+            }
+        )
+    }
+
+    pub fn is_write_only(&self) -> bool {
+        matches!(
+            self,
+            Schema::Object {
+                write_only: Some(true),
+                ..
+            }
+        )
+    }
+
     pub fn is_composition(&self) -> bool {
         matches!(
             self,
@@ -174,6 +392,22 @@ impl Schema {
 }
 
 impl OpenApiSpec {
+    /// Security requirements in force for `operation`
+    pub fn effective_security<'a>(
+        &'a self,
+        operation: &'a Operation,
+    ) -> Option<&'a [SecurityRequirement]> {
+        operation.security.as_deref().or(self.security.as_deref())
+    }
+
+    pub fn security_scheme(&self, name: &str) -> Option<&SecurityScheme> {
+        self.components
+            .as_ref()?
+            .security_schemes
+            .as_ref()?
+            .get(name)
+    }
+
     pub fn from_yaml(content: &str) -> Result<Self, OpenApiError> {
         serde_yaml::from_str(content).map_err(|e| OpenApiError::ParseError(e.to_string()))
     }
@@ -242,6 +476,72 @@ mod tests {
         assert_eq!(schema.get_reference(), Some("#/components/schemas/Task"));
     }
 
+    #[test]
+    fn test_parse_schema_metadata() {
+        let json = r#"{
+  "type": "integer",
+  "title": "Retries",
+  "description": "How many times to retry",
+  "default": 3,
+  "example": 5,
+  "deprecated": true
+}"#;
+        let schema: Schema = serde_json::from_str(json).unwrap();
+        assert_eq!(schema.title(), Some("Retries"));
+        assert_eq!(schema.description(), Some("How many times to retry"));
+        assert_eq!(schema.default_value(), Some(&serde_json::json!(3)));
+        assert_eq!(schema.examples(), vec![&serde_json::json!(5)]);
+        assert!(schema.is_deprecated());
+    }
+
+    #[test]
+    fn test_parse_security_schemes() {
+        let yaml = r#"
+openapi: "3.0.0"
+info:
+  title: "Test API"
+  version: "1.0.0"
+security:
+  - bearerAuth: []
+paths:
+  /public:
+    get:
+      security: []
+      responses:
+        "200":
+          description: "OK"
+components:
+  securitySchemes:
+    bearerAuth:
+      type: http
+      scheme: bearer
+      bearerFormat: JWT
+    apiKey:
+      type: apiKey
+      name: X-API-Key
+      in: header
+    oauth:
+      type: oauth2
+      flows:
+        clientCredentials:
+          tokenUrl: "https://auth.example.com/token"
+          scopes:
+            "jobs:read": "Read jobs"
+"#;
+        let spec = OpenApiSpec::from_yaml(yaml).unwrap();
+        assert!(matches!(
+            spec.security_scheme("apiKey"),
+            Some(SecurityScheme::ApiKey { name, in_ }) if name == "X-API-Key" && in_ == "header"
+        ));
+        assert!(matches!(
+            spec.security_scheme("oauth"),
+            Some(SecurityScheme::OAuth2 { flows }) if flows["clientCredentials"].scopes.contains_key("jobs:read")
+        ));
+
+        let public = spec.paths["/public"].get.as_ref().unwrap();
+        assert_eq!(spec.effective_security(public), Some(&[][..]));
+    }
+
     #[test]
     fn test_schema_composition() {
         let schema = Schema::AllOf { all_of: vec![] };